@@ -1,20 +1,73 @@
 pub use error::*;
+#[cfg(feature = "statement")]
+pub use statement::AccountEvent;
 pub use store::*;
 
+#[cfg(test)]
+pub(crate) mod arbitrary;
 mod error;
+#[cfg(feature = "statement")]
+mod statement;
 mod store;
 
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+};
+
 use rust_decimal::Decimal;
-use serde::{Serialize, ser::SerializeStruct};
+use serde::{Deserialize, Serialize, ser::SerializeStruct};
+
+use crate::operation::ConflictType;
+#[cfg(feature = "statement")]
+use crate::operation::TransactionId;
+
+/// Id of a client. Valid ids start at `1`, `0` is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct ClientId(pub(crate) u32);
+
+impl ClientId {
+    pub const MAX_VALUE: ClientId = ClientId(u32::MAX);
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for ClientId {
+    type Error = AccountError;
+
+    fn try_from(id: u32) -> Result<Self, Self::Error> {
+        if id == 0 {
+            return Err(AccountError::InvalidClientId { id });
+        }
+
+        Ok(ClientId(id))
+    }
+}
+
+impl From<ClientId> for u32 {
+    fn from(client: ClientId) -> Self {
+        client.0
+    }
+}
 
-pub type ClientId = u16;
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
-#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "AccountDto")]
 pub struct Account {
     client: ClientId,
     available: Decimal,
     held: Decimal,
     locked: bool,
+    #[cfg(feature = "statement")]
+    statement: statement::Statement,
 }
 
 impl Account {
@@ -24,16 +77,91 @@ impl Account {
             available: Decimal::ZERO,
             held: Decimal::ZERO,
             locked: false,
+            #[cfg(feature = "statement")]
+            statement: Default::default(),
         }
     }
 
+    /// Creates an unlocked account with the given balance, e.g. from an
+    /// external source. Fails if `held` is negative or the total overflows,
+    /// `available` may be negative as disputes can exceed the available
+    /// balance.
+    pub fn from_balance(
+        client: ClientId,
+        available: impl Into<Decimal>,
+        held: impl Into<Decimal>,
+    ) -> AccountResult<Self> {
+        let (available, held) = (available.into(), held.into());
+        if held < Decimal::ZERO {
+            return Err(AccountError::InsufficientHeld {
+                needed: Decimal::ZERO,
+                held,
+                client,
+            });
+        }
+
+        let account = Self::new(client);
+        account.check_total(available, held)?;
+        Ok(Self {
+            available,
+            held,
+            ..account
+        })
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn available(&self) -> Decimal {
+        self.available
+    }
+
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    /// Funds frozen by open disputes, which are either released back to the
+    /// available balance by a resolve or withdrawn by a chargeback. Same as
+    /// `held`.
+    pub fn pending_held_release(&self) -> Decimal {
+        self.held
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
     pub fn deposit(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
+        self.deposit_checked(amount)
+    }
+
+    pub fn withdraw(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
+        self.withdraw_checked(amount)
+    }
+
+    /// Like `deposit` but leaves the account untouched and fails with
+    /// `AccountError::Overflow` if the available balance or the total would
+    /// overflow.
+    pub fn deposit_checked(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
         let amount = amount.into();
-        self.available += amount;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or(AccountError::Overflow {
+                client: self.client,
+            })?;
+        self.check_total(available, self.held)?;
+
+        self.available = available;
+        #[cfg(feature = "statement")]
+        self.statement.0.push(AccountEvent::Deposited { amount });
         Ok(())
     }
 
-    pub fn withdraw(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
+    /// Like `withdraw` but leaves the account untouched and fails with
+    /// `AccountError::Overflow` if the available balance would overflow.
+    pub fn withdraw_checked(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
         let amount = amount.into();
         if self.locked {
             return Err(AccountError::Locked {
@@ -47,14 +175,43 @@ impl Account {
             });
         }
 
-        self.available -= amount;
+        self.available = self
+            .available
+            .checked_sub(amount)
+            .ok_or(AccountError::Overflow {
+                client: self.client,
+            })?;
+        #[cfg(feature = "statement")]
+        self.statement.0.push(AccountEvent::Withdrawn { amount });
         Ok(())
     }
 
+    /// Returns `true` if `withdraw` would succeed for the given amount.
+    pub fn can_withdraw(&self, amount: impl Into<Decimal>) -> bool {
+        !self.locked && self.available >= amount.into()
+    }
+
+    /// Returns `true` if the available funds cover a dispute of the given
+    /// amount. A dispute is still issued if that's not the case, but it
+    /// leads to a negative available balance.
+    pub fn can_dispute(&self, amount: impl Into<Decimal>) -> bool {
+        self.available >= amount.into()
+    }
+
+    /// Moves the amount from the available to the held balance. Fails with
+    /// `AccountError::Overflow` and leaves the account untouched if a
+    /// balance would overflow.
     pub fn dispute(&mut self, amount: impl Into<Decimal>) -> AccountResult<()> {
         let amount = amount.into();
-        self.available -= amount;
-        self.held += amount;
+        let overflow = AccountError::Overflow {
+            client: self.client,
+        };
+        let available = self.available.checked_sub(amount).ok_or(overflow.clone())?;
+        let held = self.held.checked_add(amount).ok_or(overflow)?;
+        self.check_total(available, held)?;
+
+        self.available = available;
+        self.held = held;
         Ok(())
     }
 
@@ -68,7 +225,12 @@ impl Account {
             });
         }
 
-        self.available += amount;
+        self.available = self
+            .available
+            .checked_add(amount)
+            .ok_or(AccountError::Overflow {
+                client: self.client,
+            })?;
         self.held -= amount;
         Ok(())
     }
@@ -88,53 +250,297 @@ impl Account {
         Ok(())
     }
 
+    /// Applies the account side of a conflict with the given amount, i.e.
+    /// `dispute`, `release` or `chargeback`. The dispute state of the
+    /// transaction itself is tracked by the caller.
+    pub fn apply_conflict(
+        &mut self,
+        conflict_type: ConflictType,
+        amount: impl Into<Decimal>,
+    ) -> AccountResult<()> {
+        match conflict_type {
+            ConflictType::Dispute => self.dispute(amount),
+            ConflictType::Resolve => self.release(amount),
+            ConflictType::Chargeback => self.chargeback(amount),
+        }
+    }
+
+    // Records a conflict on the transaction `tx` which was applied with
+    // `apply_conflict`. Only the caller knows the transaction, so conflicts
+    // aren't recorded by the account itself. The lock of a chargeback is
+    // recorded unless the account is already recorded as locked.
+    #[cfg(feature = "statement")]
+    pub(crate) fn record_conflict(
+        &mut self,
+        tx: TransactionId,
+        conflict_type: ConflictType,
+        amount: Decimal,
+    ) {
+        let event = match conflict_type {
+            ConflictType::Dispute => AccountEvent::DisputeOpened { tx, amount },
+            ConflictType::Resolve => AccountEvent::DisputeResolved { tx, amount },
+            ConflictType::Chargeback => AccountEvent::ChargedBack { tx, amount },
+        };
+        self.statement.0.push(event);
+        if self.locked && !self.statement.is_locked() {
+            self.statement.0.push(AccountEvent::Locked);
+        }
+    }
+
+    /// All changes applied to the account in the order they were applied.
+    /// Conflicts are recorded by the `PaymentProcessor` together with their
+    /// transaction, applying them directly to the account doesn't record
+    /// them.
+    #[cfg(feature = "statement")]
+    pub fn statement(&self) -> Vec<AccountEvent> {
+        self.statement.0.clone()
+    }
+
+    // Restores an account from a checkpoint without any validation.
+    pub(crate) fn restore(
+        client: ClientId,
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+    ) -> Self {
+        Self {
+            available,
+            held,
+            locked,
+            ..Self::new(client)
+        }
+    }
+
+    /// Clears the locked flag, e.g. to reinstate an account after a
+    /// chargeback was investigated. The balance is not checked, so
+    /// accounts with a negative available balance can be unlocked too.
+    pub fn unlock(&mut self) {
+        #[cfg(feature = "statement")]
+        if self.locked {
+            self.statement.0.push(AccountEvent::Unlocked);
+        }
+        self.locked = false;
+    }
+
+    // Adds the balances of `other` and locks the account if `other` is
+    // locked. Leaves the account untouched if a balance would overflow.
+    pub(crate) fn add_balance(&mut self, other: &Account) -> AccountResult<()> {
+        let overflow = AccountError::Overflow {
+            client: self.client,
+        };
+        let available = self
+            .available
+            .checked_add(other.available)
+            .ok_or(overflow.clone())?;
+        let held = self.held.checked_add(other.held).ok_or(overflow)?;
+        self.check_total(available, held)?;
+
+        self.available = available;
+        self.held = held;
+        self.locked |= other.locked;
+        Ok(())
+    }
+
+    // Fails with `AccountError::Overflow` if the total of the given balances
+    // isn't representable. Every change is checked so `total` can't overflow.
+    fn check_total(&self, available: Decimal, held: Decimal) -> AccountResult<()> {
+        match available.checked_add(held) {
+            Some(_) => Ok(()),
+            None => Err(AccountError::Overflow {
+                client: self.client,
+            }),
+        }
+    }
+
+    /// Sum of the available and held balance. Changes which would make it
+    /// overflow are rejected, so it's always representable.
     pub fn total(&self) -> Decimal {
         self.available + self.held
     }
 
+    /// The maximal amount that can currently be withdrawn.
+    pub fn debit_capacity(&self) -> Decimal {
+        if self.locked {
+            return Decimal::ZERO;
+        }
+
+        self.available.max(Decimal::ZERO)
+    }
+
     #[cfg(test)]
     pub fn create(
-        client: u16,
+        client: u32,
         available: impl Into<Decimal>,
         held: impl Into<Decimal>,
         locked: bool,
     ) -> Self {
         Self {
-            client,
+            client: ClientId(client),
             available: available.into(),
             held: held.into(),
             locked,
+            #[cfg(feature = "statement")]
+            statement: Default::default(),
         }
     }
 }
 
+// The statement only documents how the state of an account came to
+// be, so accounts are compared and hashed by their state alone.
+impl PartialEq for Account {
+    fn eq(&self, other: &Self) -> bool {
+        self.client == other.client
+            && self.available == other.available
+            && self.held == other.held
+            && self.locked == other.locked
+    }
+}
+
+impl Eq for Account {}
+
+impl Hash for Account {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.client.hash(state);
+        self.available.hash(state);
+        self.held.hash(state);
+        self.locked.hash(state);
+    }
+}
+
 impl Serialize for Account {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        // TODO: 4 decimal places precision
         let mut s = serializer.serialize_struct("Account", 5)?;
         s.serialize_field("client", &self.client)?;
-        s.serialize_field("available", &self.available)?;
-        s.serialize_field("held", &self.held)?;
-        s.serialize_field("total", &self.total())?;
+        s.serialize_field("available", &four_decimal_places(self.available))?;
+        s.serialize_field("held", &four_decimal_places(self.held))?;
+        s.serialize_field("total", &four_decimal_places(self.total()))?;
         s.serialize_field("locked", &self.locked)?;
         s.end()
     }
 }
 
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Account[client={}, available={}, held={}, total={}, locked={}]",
+            self.client,
+            four_decimal_places(self.available),
+            four_decimal_places(self.held),
+            four_decimal_places(self.total()),
+            self.locked
+        )
+    }
+}
+
+// The serialized form of an `Account`, which is validated before it's
+// turned into one.
+#[derive(Deserialize)]
+struct AccountDto {
+    client: ClientId,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl TryFrom<AccountDto> for Account {
+    type Error = AccountError;
+
+    fn try_from(dto: AccountDto) -> Result<Self, Self::Error> {
+        let account = Self::from_balance(dto.client, dto.available, dto.held)?;
+
+        if account.total() != dto.total {
+            return Err(AccountError::TotalMismatch {
+                client: dto.client,
+                available: dto.available,
+                held: dto.held,
+                total: dto.total,
+            });
+        }
+
+        Ok(Self {
+            locked: dto.locked,
+            ..account
+        })
+    }
+}
+
+// Amounts are always emitted with exactly four decimal places. Values with
+// a higher precision (e.g. from arithmetic) are rounded, values with a lower
+// precision are padded with zeros so `10` becomes `10.0000`.
+fn four_decimal_places(value: Decimal) -> Decimal {
+    let mut value = value.round_dp(4);
+    value.rescale(4);
+    value
+}
+
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
+    use crate::{csv_reader_builder, operation::arbitrary::amount};
+
     use super::*;
 
+    #[derive(Debug, Clone, Copy)]
+    enum AccountOperation {
+        Deposit(Decimal),
+        Withdraw(Decimal),
+        Dispute(Decimal),
+        Release(Decimal),
+        Chargeback(Decimal),
+    }
+
+    fn account_operation() -> impl Strategy<Value = AccountOperation> {
+        prop_oneof![
+            amount().prop_map(AccountOperation::Deposit),
+            amount().prop_map(AccountOperation::Withdraw),
+            amount().prop_map(AccountOperation::Dispute),
+            amount().prop_map(AccountOperation::Release),
+            amount().prop_map(AccountOperation::Chargeback),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn invariants(operations in prop::collection::vec(account_operation(), 0..50)) {
+            let mut account = Account::new(ClientId(1));
+            for operation in operations {
+                let before = account.clone();
+                let result = match operation {
+                    AccountOperation::Deposit(amount) => account.deposit(amount),
+                    AccountOperation::Withdraw(amount) => account.withdraw(amount),
+                    AccountOperation::Dispute(amount) => account.dispute(amount),
+                    AccountOperation::Release(amount) => account.release(amount),
+                    AccountOperation::Chargeback(amount) => account.chargeback(amount),
+                };
+
+                prop_assert_eq!(account.total(), account.available() + account.held());
+                prop_assert!(account.held() >= Decimal::ZERO);
+                // only an explicit unlock clears the lock
+                prop_assert!(account.is_locked() || !before.is_locked());
+                if result.is_err() {
+                    prop_assert_eq!(&account, &before);
+                } else if let AccountOperation::Chargeback(_) = operation {
+                    prop_assert!(account.is_locked());
+                }
+            }
+        }
+    }
+
     #[test]
     fn locked() {
         let mut account = Account::create(1, 10, 0, true);
 
         assert_eq!(
             account.withdraw(42),
-            Err(AccountError::Locked { client: 1 })
+            Err(AccountError::Locked {
+                client: ClientId(1)
+            })
         );
 
         account.deposit(5).unwrap();
@@ -145,6 +551,56 @@ mod test {
         assert_eq!(account, Account::create(1, -10, 15, true));
     }
 
+    #[test]
+    fn predicates() {
+        let account = Account::create(1, 10, 5, false);
+        assert!(account.can_withdraw(10));
+        assert!(!account.can_withdraw(11));
+        assert!(account.can_dispute(10));
+        assert!(!account.can_dispute(11));
+
+        let account = Account::create(1, 10, 5, true);
+        assert!(!account.can_withdraw(1));
+        assert!(account.can_dispute(10));
+        assert_eq!(account.pending_held_release(), account.held());
+    }
+
+    #[test]
+    fn debit_capacity() {
+        assert_eq!(
+            Account::create(1, 10, 5, false).debit_capacity(),
+            Decimal::from(10)
+        );
+        assert_eq!(
+            Account::create(1, 10, 5, true).debit_capacity(),
+            Decimal::ZERO
+        );
+        assert_eq!(
+            Account::create(1, -10, 5, false).debit_capacity(),
+            Decimal::ZERO
+        );
+    }
+
+    #[test]
+    fn unlock() {
+        let mut account = Account::create(1, 10, 10, false);
+        account.chargeback(10).unwrap();
+        account.dispute(15).unwrap();
+        assert_eq!(
+            account.withdraw(1),
+            Err(AccountError::Locked {
+                client: ClientId(1)
+            })
+        );
+
+        account.unlock();
+        assert_eq!(account, Account::create(1, -5, 15, false));
+
+        account.deposit(10).unwrap();
+        account.withdraw(5).unwrap();
+        assert_eq!(account, Account::create(1, 0, 15, false));
+    }
+
     #[test]
     fn exceed_balance() {
         let mut account = Account::create(1, 10, 20, false);
@@ -152,7 +608,7 @@ mod test {
         assert_eq!(
             account.withdraw(42),
             Err(AccountError::InsufficientAvailable {
-                client: 1,
+                client: ClientId(1),
                 needed: 42.into(),
                 available: 10.into(),
             })
@@ -160,7 +616,7 @@ mod test {
         assert_eq!(
             account.release(42),
             Err(AccountError::InsufficientHeld {
-                client: 1,
+                client: ClientId(1),
                 needed: 42.into(),
                 held: 20.into(),
             })
@@ -168,7 +624,7 @@ mod test {
         assert_eq!(
             account.chargeback(42),
             Err(AccountError::InsufficientHeld {
-                client: 1,
+                client: ClientId(1),
                 needed: 42.into(),
                 held: 20.into(),
             })
@@ -177,7 +633,7 @@ mod test {
 
     #[test]
     fn payment_flow() {
-        let mut account = Account::new(1);
+        let mut account = Account::new(ClientId(1));
 
         account.deposit(100).unwrap();
         assert_eq!(account, Account::create(1, 100, 0, false));
@@ -196,7 +652,7 @@ mod test {
             Err(AccountError::InsufficientAvailable {
                 needed: 25.into(),
                 available: Decimal::from(10),
-                client: 1
+                client: ClientId(1)
             })
         );
 
@@ -218,7 +674,12 @@ mod test {
         account.deposit(20).unwrap();
         assert_eq!(account, Account::create(1, 10, 30, true));
 
-        assert_eq!(account.withdraw(5), Err(AccountError::Locked { client: 1 }));
+        assert_eq!(
+            account.withdraw(5),
+            Err(AccountError::Locked {
+                client: ClientId(1)
+            })
+        );
 
         account.dispute(15).unwrap();
         assert_eq!(account, Account::create(1, -5, 45, true));
@@ -232,4 +693,306 @@ mod test {
         account.chargeback(10).unwrap();
         assert_eq!(account, Account::create(1, 10, 20, true));
     }
+
+    #[test]
+    fn client_id() {
+        assert_eq!(
+            ClientId::try_from(0),
+            Err(AccountError::InvalidClientId { id: 0 })
+        );
+        assert_eq!(ClientId::try_from(1).map(ClientId::get), Ok(1));
+        assert_eq!(ClientId::MAX_VALUE.get(), u32::MAX);
+
+        let data = "\
+client,available,held,total,locked
+0,0,0,0,false
+";
+        assert!(
+            csv_reader_builder()
+                .from_reader(data.as_bytes())
+                .deserialize::<Account>()
+                .all(|res| res.is_err())
+        );
+
+        let data = "\
+client,available,held,total,locked
+70000,0,0,0,false
+";
+        assert_eq!(
+            csv_reader_builder()
+                .from_reader(data.as_bytes())
+                .deserialize::<Account>()
+                .map(|res| res.map(|account| account.client.get()))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            vec![70000]
+        );
+    }
+
+    #[test]
+    fn from_balance() {
+        assert_eq!(
+            Account::from_balance(ClientId(1), -5, 10),
+            Ok(Account::create(1, -5, 10, false))
+        );
+        assert_eq!(
+            Account::from_balance(ClientId(1), 5, Decimal::ZERO),
+            Ok(Account::create(1, 5, 0, false))
+        );
+        assert_eq!(
+            Account::from_balance(ClientId(1), 5, -1),
+            Err(AccountError::InsufficientHeld {
+                needed: Decimal::ZERO,
+                held: (-1).into(),
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn apply_conflict() {
+        let mut account = Account::create(1, 10, 0, false);
+
+        account.apply_conflict(ConflictType::Dispute, 4).unwrap();
+        assert_eq!(account, Account::create(1, 6, 4, false));
+        account.apply_conflict(ConflictType::Resolve, 4).unwrap();
+        assert_eq!(account, Account::create(1, 10, 0, false));
+        assert_eq!(
+            account.apply_conflict(ConflictType::Chargeback, 4),
+            Err(AccountError::InsufficientHeld {
+                needed: 4.into(),
+                held: 0.into(),
+                client: ClientId(1)
+            })
+        );
+        account.apply_conflict(ConflictType::Dispute, 4).unwrap();
+        account.apply_conflict(ConflictType::Chargeback, 4).unwrap();
+        assert_eq!(account, Account::create(1, 6, 0, true));
+    }
+
+    #[cfg(feature = "statement")]
+    #[test]
+    fn statement() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(10).unwrap();
+        account.withdraw(3).unwrap();
+        account.withdraw(30).unwrap_err();
+        for conflict_type in [
+            ConflictType::Dispute,
+            ConflictType::Resolve,
+            ConflictType::Dispute,
+            ConflictType::Chargeback,
+        ] {
+            account.apply_conflict(conflict_type, 5).unwrap();
+            account.record_conflict(TransactionId(1), conflict_type, 5.into());
+        }
+        account.unlock();
+        account.unlock();
+
+        let amount = Decimal::from(5);
+        let tx = TransactionId(1);
+        assert_eq!(
+            account.statement(),
+            vec![
+                AccountEvent::Deposited { amount: 10.into() },
+                AccountEvent::Withdrawn { amount: 3.into() },
+                AccountEvent::DisputeOpened { tx, amount },
+                AccountEvent::DisputeResolved { tx, amount },
+                AccountEvent::DisputeOpened { tx, amount },
+                AccountEvent::ChargedBack { tx, amount },
+                AccountEvent::Locked,
+                AccountEvent::Unlocked,
+            ]
+        );
+        // the statement is not part of the state
+        assert_ne!(account.statement, Account::create(1, 2, 0, false).statement);
+        assert_eq!(account, Account::create(1, 2, 0, false));
+
+        // conflicts applied directly aren't recorded
+        let mut account = Account::new(ClientId(1));
+        account.deposit(10).unwrap();
+        account.dispute(5).unwrap();
+        account.chargeback(5).unwrap();
+        assert_eq!(
+            account.statement(),
+            vec![AccountEvent::Deposited { amount: 10.into() }]
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut account = Account::create(1, Decimal::MAX, 0, false);
+
+        assert_eq!(
+            account.deposit_checked(1),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(
+            account.deposit(1),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account.available(), Decimal::MAX);
+
+        account.withdraw_checked(Decimal::MAX).unwrap();
+        assert_eq!(account.available(), Decimal::ZERO);
+
+        // negative amounts are rejected when parsing but not by the account
+        let mut account = Account::create(1, Decimal::MAX, 0, false);
+        assert_eq!(
+            account.withdraw_checked(Decimal::MIN),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account.available(), Decimal::MAX);
+
+        let mut account = Account::create(1, Decimal::MIN, 0, false);
+        assert_eq!(
+            account.dispute(1),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account, Account::create(1, Decimal::MIN, 0, false));
+
+        // the total of the available and held balance has to fit as well
+        let mut account = Account::new(ClientId(1));
+        account.deposit(10).unwrap();
+        account.dispute(10).unwrap();
+        assert_eq!(
+            account.deposit(Decimal::MAX - Decimal::from(5)),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account, Account::create(1, 0, 10, false));
+        assert_eq!(account.total(), Decimal::from(10));
+        assert_eq!(
+            Account::from_balance(ClientId(1), Decimal::MAX, 1),
+            Err(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Account::create(1, 10, Decimal::new(25, 1), false).to_string(),
+            "Account[client=1, available=10.0000, held=2.5000, total=12.5000, locked=false]"
+        );
+    }
+
+    #[test]
+    fn serialize_four_decimal_places() {
+        let accounts = [
+            Account::create(1, 10, 0, false),
+            Account::create(2, Decimal::new(-105, 1), Decimal::new(123456, 5), true),
+        ];
+
+        let mut output = Vec::new();
+        {
+            let mut writer = csv::Writer::from_writer(&mut output);
+            for account in accounts {
+                writer.serialize(account).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+client,available,held,total,locked
+1,10.0000,0.0000,10.0000,false
+2,-10.5000,1.2346,-9.2654,true
+"
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let data = "\
+client,available,held,total,locked
+1,10.0000,0.0000,10.0000,false
+2,-10.5,1.25,-9.25,true
+3,1,2,4,false
+4,50000000000000000000000000000.0,50000000000000000000000000000.0,1,false
+5,10,-1,9,false
+";
+
+        let accounts = crate::csv_reader_builder()
+            .from_reader(data.as_bytes())
+            .into_deserialize::<Account>()
+            .map(|res| res.map_err(|err| err.to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(accounts[0], Ok(Account::create(1, 10, 0, false)));
+        assert_eq!(
+            accounts[1],
+            Ok(Account::create(
+                2,
+                Decimal::new(-105, 1),
+                Decimal::new(125, 2),
+                true
+            ))
+        );
+        assert!(
+            accounts[2].as_ref().unwrap_err().contains(
+                &AccountError::TotalMismatch {
+                    client: ClientId(3),
+                    available: 1.into(),
+                    held: 2.into(),
+                    total: 4.into(),
+                }
+                .to_string()
+            )
+        );
+        assert!(
+            accounts[3].as_ref().unwrap_err().contains(
+                &AccountError::Overflow {
+                    client: ClientId(4)
+                }
+                .to_string()
+            )
+        );
+        assert!(
+            accounts[4].as_ref().unwrap_err().contains(
+                &AccountError::InsufficientHeld {
+                    needed: Decimal::ZERO,
+                    held: (-1).into(),
+                    client: ClientId(5),
+                }
+                .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let accounts = vec![
+            Account::create(1, 10, 0, false),
+            Account::create(2, Decimal::new(-105, 1), Decimal::new(12345, 4), true),
+        ];
+
+        let mut output = Vec::new();
+        {
+            let mut writer = csv::Writer::from_writer(&mut output);
+            for account in accounts.iter() {
+                writer.serialize(account).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+
+        let deserialized = crate::csv_reader_builder()
+            .from_reader(output.as_slice())
+            .into_deserialize::<Account>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(deserialized, accounts);
+    }
 }
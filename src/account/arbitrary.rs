@@ -0,0 +1,40 @@
+use proptest::{arbitrary::Arbitrary, prelude::*, strategy::BoxedStrategy};
+use rust_decimal::Decimal;
+
+use super::{Account, ClientId};
+
+/// Any whole `i64` or an `i64` with up to four decimal places.
+pub(crate) fn decimal() -> impl Strategy<Value = Decimal> {
+    prop_oneof![
+        any::<i64>().prop_map(Decimal::from),
+        (any::<i64>(), 1..=4u32).prop_map(|(num, scale)| Decimal::new(num, scale)),
+    ]
+}
+
+// Ids are drawn from a small range so that generated operations share
+// clients and generated accounts collide.
+impl Arbitrary for ClientId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (1..=20u32).prop_map(ClientId).boxed()
+    }
+}
+
+impl Arbitrary for Account {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (any::<ClientId>(), decimal(), decimal(), any::<bool>())
+            .prop_map(|(client, available, held, locked)| {
+                let mut account = Account::new(client);
+                account.available = available;
+                account.held = held.abs();
+                account.locked = locked;
+                account
+            })
+            .boxed()
+    }
+}
@@ -5,8 +5,10 @@ use super::ClientId;
 
 pub type AccountResult<T> = Result<T, AccountError>;
 
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum AccountError {
+    #[error("invalid client id '{id}'")]
+    InvalidClientId { id: u32 },
     #[error(
         "account '{client}' has insuffiecient available funds '{available}', needed: '{needed}'"
     )]
@@ -21,6 +23,17 @@ pub enum AccountError {
         held: Decimal,
         client: ClientId,
     },
+    #[error("account '{client}' balance would overflow")]
+    Overflow { client: ClientId },
     #[error("account '{client}' locked")]
     Locked { client: ClientId },
+    #[error(
+        "account '{client}' has a total of '{total}' which doesn't match available '{available}' plus held '{held}'"
+    )]
+    TotalMismatch {
+        client: ClientId,
+        available: Decimal,
+        held: Decimal,
+        total: Decimal,
+    },
 }
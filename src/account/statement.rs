@@ -0,0 +1,35 @@
+use rust_decimal::Decimal;
+
+use crate::operation::TransactionId;
+
+/// A single change applied to an account, see `Account::statement`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AccountEvent {
+    Deposited { amount: Decimal },
+    Withdrawn { amount: Decimal },
+    DisputeOpened { tx: TransactionId, amount: Decimal },
+    DisputeResolved { tx: TransactionId, amount: Decimal },
+    ChargedBack { tx: TransactionId, amount: Decimal },
+    Locked,
+    Unlocked,
+}
+
+// The events of an account, which are not part of the comparison
+// or the hash of the account, see the impls of `Account`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub(super) struct Statement(pub(super) Vec<AccountEvent>);
+
+impl Statement {
+    // Whether the last recorded lock change locked the account.
+    pub(super) fn is_locked(&self) -> bool {
+        self.0
+            .iter()
+            .rev()
+            .find_map(|event| match event {
+                AccountEvent::Locked => Some(true),
+                AccountEvent::Unlocked => Some(false),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+}
@@ -1,19 +1,619 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs::File,
+    io::Read,
+    path::Path,
+};
 
 use derive_more::IntoIterator;
+use rust_decimal::Decimal;
+
+use crate::{EngineResult, PaymentError, PaymentResult, csv_reader_builder};
 
 use super::{Account, ClientId};
 
-#[derive(Default, Debug, IntoIterator)]
+#[derive(Default, Debug, Clone, IntoIterator)]
 #[into_iterator(owned, ref, ref_mut)]
 pub struct AccountStore(HashMap<ClientId, Account>);
 
+/// How `AccountStore::merge` handles clients which exist in both stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The account of the other store replaces the existing one.
+    Overwrite,
+    /// The existing account is kept, the one of the other store is dropped.
+    KeepExisting,
+    /// The available and held balances of both accounts are added up, e.g.
+    /// to merge per-day snapshots. The account is locked if either is.
+    SumBalances,
+    /// Merging fails with `PaymentError::DuplicateAccount`, same as `extend`.
+    Fail,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    pub total_available: Decimal,
+    pub total_held: Decimal,
+    pub total_balance: Decimal,
+    pub accounts: usize,
+    pub locked_accounts: usize,
+}
+
 impl AccountStore {
+    /// Loads accounts previously written as CSV with the columns
+    /// `client,available,held,total,locked`, e.g. to continue processing
+    /// from a saved state. Fails on malformed rows and if a client appears
+    /// more than once in the file, accounts of other sources aren't checked.
+    pub fn load_from_csv<P: AsRef<Path>>(path: P) -> EngineResult<AccountStore> {
+        let file = File::open(path)?;
+        Self::load_from_reader(file)
+    }
+
+    pub fn load_from_reader<R: Read>(reader: R) -> EngineResult<AccountStore> {
+        let mut store = AccountStore::default();
+        for account in csv_reader_builder()
+            .from_reader(reader)
+            .into_deserialize::<Account>()
+        {
+            let account = account?;
+            let client = account.client();
+            if store.insert(account).is_some() {
+                return Err(PaymentError::DuplicateAccount { client }.into());
+            }
+        }
+
+        Ok(store)
+    }
+
+    pub fn get(&self, client: ClientId) -> Option<&Account> {
+        self.0.get(&client)
+    }
+
+    pub fn contains_client(&self, client: ClientId) -> bool {
+        self.0.contains_key(&client)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Approximate heap usage of the store in bytes, based on the capacity
+    /// of the underlying hash map and one control byte per entry. Heap
+    /// allocations of the accounts themselves aren't included.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        self.0.capacity() * (size_of::<(ClientId, Account)>() + 1)
+    }
+
+    /// Sum of the available balances, `None` if it overflows.
+    pub fn total_available(&self) -> Option<Decimal> {
+        checked_sum(self.0.values().map(Account::available))
+    }
+
+    /// Sum of the held balances, `None` if it overflows.
+    pub fn total_held(&self) -> Option<Decimal> {
+        checked_sum(self.0.values().map(Account::held))
+    }
+
+    /// Sum of the total balances, `None` if it overflows.
+    pub fn total_balance(&self) -> Option<Decimal> {
+        checked_sum(self.0.values().map(Account::total))
+    }
+
+    /// Bundles the totals and counts commonly needed to reconcile the store.
+    /// `None` if one of the totals overflows.
+    pub fn reconciliation_report(&self) -> Option<ReconciliationReport> {
+        Some(ReconciliationReport {
+            total_available: self.total_available()?,
+            total_held: self.total_held()?,
+            total_balance: self.total_balance()?,
+            accounts: self.len(),
+            locked_accounts: self.0.values().filter(|acc| acc.is_locked()).count(),
+        })
+    }
+
+    /// Inserts the account, replacing and returning
+    /// a previous account of the same client.
+    pub fn insert(&mut self, account: Account) -> Option<Account> {
+        self.0.insert(account.client(), account)
+    }
+
     pub fn get_mut(&mut self, client: ClientId) -> &mut Account {
         self.0.entry(client).or_insert_with(|| Account::new(client))
     }
 
-    pub fn extend(&mut self, other: Self) {
+    pub fn retain(&mut self, f: impl Fn(ClientId, &Account) -> bool) {
+        self.0.retain(|client, account| f(*client, account));
+    }
+
+    /// Removes all accounts but keeps the allocated memory.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Moves all accounts of `other` into the store. Fails if any client
+    /// exists in both stores, the store is unchanged in that case.
+    pub fn extend(&mut self, other: Self) -> PaymentResult<()> {
+        if let Some(client) = other.0.keys().find(|client| self.0.contains_key(client)) {
+            return Err(PaymentError::DuplicateAccount { client: *client });
+        }
+
         self.0.extend(other.0);
+        Ok(())
+    }
+
+    /// Moves all accounts of `other` into the store and resolves clients
+    /// which exist in both stores according to `strategy`. On failure the
+    /// store is unchanged.
+    pub fn merge(&mut self, other: Self, strategy: MergeStrategy) -> PaymentResult<()> {
+        match strategy {
+            MergeStrategy::Overwrite => self.0.extend(other.0),
+            MergeStrategy::KeepExisting => {
+                for (client, account) in other.0 {
+                    self.0.entry(client).or_insert(account);
+                }
+            }
+            MergeStrategy::SumBalances => {
+                // all sums are computed before the first one is stored
+                let merged = other
+                    .0
+                    .into_values()
+                    .map(|account| match self.0.get(&account.client()) {
+                        Some(existing) => {
+                            let mut existing = existing.clone();
+                            existing
+                                .add_balance(&account)
+                                .map_err(PaymentError::Merge)?;
+                            Ok(existing)
+                        }
+                        None => Ok(account),
+                    })
+                    .collect::<PaymentResult<Vec<_>>>()?;
+                self.extend_from_iter(merged);
+            }
+            MergeStrategy::Fail => self.extend(other)?,
+        }
+
+        Ok(())
+    }
+
+    /// Moves the given accounts into the store, in contrast to `extend`
+    /// existing accounts of the same client are overwritten.
+    pub fn extend_from_iter(&mut self, accounts: impl IntoIterator<Item = Account>) {
+        self.0.extend(
+            accounts
+                .into_iter()
+                .map(|account| (account.client(), account)),
+        );
+    }
+
+    /// Returns at most `n` accounts with the highest total balance in
+    /// descending order. Ties are ordered by client id.
+    pub fn top_n_by_balance(&self, n: usize) -> Vec<(ClientId, &Account)> {
+        self.n_largest_by(n, |acc| acc.total())
+    }
+
+    /// Returns at most `n` accounts with the lowest, potentially negative,
+    /// total balance in ascending order. Ties are ordered by client id.
+    pub fn bottom_n_by_balance(&self, n: usize) -> Vec<(ClientId, &Account)> {
+        self.n_largest_by(n, |acc| Reverse(acc.total()))
+    }
+
+    // Keeps the `n` largest accounts in a min heap, so only `n` accounts
+    // are held at a time instead of sorting the whole store.
+    fn n_largest_by<K: Ord>(
+        &self,
+        n: usize,
+        key: impl Fn(&Account) -> K,
+    ) -> Vec<(ClientId, &Account)> {
+        let mut heap = BinaryHeap::with_capacity(n.min(self.len()) + 1);
+        for (client, account) in self.0.iter() {
+            heap.push(Reverse((key(account), Reverse(*client))));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, Reverse(client)))| (client, &self.0[&client]))
+            .collect()
+    }
+
+    /// Consumes the store and returns all accounts sorted by client id.
+    pub fn into_sorted_vec(self) -> Vec<Account> {
+        let mut accounts = self.0.into_values().collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(Account::client);
+        accounts
+    }
+
+    /// Iterates over all accounts sorted by client id.
+    pub fn sorted_iter(&self) -> impl Iterator<Item = &Account> {
+        let mut accounts = self.0.values().collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(|acc| acc.client());
+        accounts.into_iter()
+    }
+
+    /// Splits the store into `(locked, unlocked)` accounts.
+    pub fn partition_by_locked(self) -> (AccountStore, AccountStore) {
+        let (locked, unlocked) = self.0.into_iter().partition(|(_, acc)| acc.is_locked());
+        (AccountStore(locked), AccountStore(unlocked))
+    }
+
+    /// Same as `partition_by_locked` but without consuming the store.
+    pub fn split_by_locked(&self) -> (Vec<&Account>, Vec<&Account>) {
+        self.0.values().partition(|acc| acc.is_locked())
+    }
+
+    /// All accounts with a negative available balance, i.e. overdrawn by
+    /// disputes, sorted by client id.
+    pub fn find_negative_balance_accounts(&self) -> Vec<&Account> {
+        self.sorted_iter()
+            .filter(|acc| acc.available() < Decimal::ZERO)
+            .collect()
+    }
+
+    /// All accounts with a negative total balance sorted by client id. This
+    /// should never be the case and indicates corrupted data.
+    pub fn find_negative_total_accounts(&self) -> Vec<&Account> {
+        self.sorted_iter()
+            .filter(|acc| acc.total() < Decimal::ZERO)
+            .collect()
+    }
+}
+
+// Sums the values, `None` if the sum overflows.
+fn checked_sum(mut values: impl Iterator<Item = Decimal>) -> Option<Decimal> {
+    values.try_fold(Decimal::ZERO, Decimal::checked_add)
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{EngineError, account::AccountError};
+
+    fn store() -> impl Strategy<Value = AccountStore> {
+        prop::collection::vec(any::<Account>(), 0..50).prop_map(|accounts| {
+            let mut store = AccountStore::default();
+            store.extend_from_iter(accounts);
+            store
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_store(store in store()) {
+            let report = store.reconciliation_report().unwrap();
+            prop_assert_eq!(report.accounts, store.len());
+            prop_assert_eq!(report.total_balance, report.total_available + report.total_held);
+            prop_assert!(report.total_held >= Decimal::ZERO);
+
+            let top = store.top_n_by_balance(store.len());
+            prop_assert_eq!(top.len(), store.len());
+            prop_assert!(top.windows(2).all(|w| w[0].1.total() >= w[1].1.total()));
+
+            let (locked, unlocked) = store.split_by_locked();
+            prop_assert_eq!(locked.len(), report.locked_accounts);
+            prop_assert_eq!(locked.len() + unlocked.len(), store.len());
+
+            let sorted = store.into_sorted_vec();
+            prop_assert!(sorted.windows(2).all(|w| w[0].client() < w[1].client()));
+        }
+    }
+
+    #[test]
+    fn get_does_not_insert() {
+        let mut store = AccountStore::default();
+
+        assert_eq!(store.get(ClientId(1)), None);
+        assert!(!store.contains_client(ClientId(1)));
+
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+
+        assert_eq!(
+            store.get(ClientId(1)),
+            Some(&Account::create(1, 10, 0, false))
+        );
+        assert!(store.contains_client(ClientId(1)));
+        assert!(!store.contains_client(ClientId(2)));
+    }
+
+    #[test]
+    fn totals() {
+        let mut store = AccountStore::default();
+        assert!(store.is_empty());
+        assert_eq!(store.total_balance(), Some(Decimal::ZERO));
+
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+        store.get_mut(ClientId(2)).deposit(20).unwrap();
+        store.get_mut(ClientId(2)).dispute(25).unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(!store.is_empty());
+        assert_eq!(store.total_available(), Some(5.into()));
+        assert_eq!(store.total_held(), Some(25.into()));
+        assert_eq!(store.total_balance(), Some(30.into()));
+
+        store.get_mut(ClientId(3)).chargeback(0).unwrap();
+        assert_eq!(
+            store.reconciliation_report(),
+            Some(ReconciliationReport {
+                total_available: 5.into(),
+                total_held: 25.into(),
+                total_balance: 30.into(),
+                accounts: 3,
+                locked_accounts: 1,
+            })
+        );
+
+        // the accounts are valid on their own but their sum overflows
+        store.insert(Account::create(4, Decimal::MAX, 0, false));
+        assert_eq!(store.total_available(), None);
+        assert_eq!(store.total_held(), Some(25.into()));
+        assert_eq!(store.total_balance(), None);
+        assert_eq!(store.reconciliation_report(), None);
+    }
+
+    #[test]
+    fn load_from_reader() {
+        let data = "\
+client,available,held,total,locked
+1,10.0000,0.0000,10.0000,false
+2,-5,5,0,true
+";
+
+        let store = AccountStore::load_from_reader(data.as_bytes()).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.get(ClientId(1)),
+            Some(&Account::create(1, 10, 0, false))
+        );
+        assert_eq!(
+            store.get(ClientId(2)),
+            Some(&Account::create(2, -5, 5, true))
+        );
+
+        let duplicate = "\
+client,available,held,total,locked
+1,10,0,10,false
+1,10,0,10,false
+";
+        assert!(matches!(
+            AccountStore::load_from_reader(duplicate.as_bytes()),
+            Err(EngineError::Processing(PaymentError::DuplicateAccount {
+                client: ClientId(1)
+            }))
+        ));
+
+        let mismatch = "\
+client,available,held,total,locked
+1,10,0,11,false
+";
+        assert!(AccountStore::load_from_reader(mismatch.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn retain() {
+        let mut store = AccountStore::default();
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+        store.get_mut(ClientId(2));
+        store.get_mut(ClientId(3)).chargeback(0).unwrap();
+
+        store.retain(|_, acc| !acc.available().is_zero() || acc.is_locked());
+
+        assert!(store.contains_client(ClientId(1)));
+        assert!(!store.contains_client(ClientId(2)));
+        assert!(store.contains_client(ClientId(3)));
+
+        store.retain(|client, _| client != ClientId(3));
+
+        assert_eq!(store.len(), 1);
+        assert!(store.contains_client(ClientId(1)));
+    }
+
+    #[test]
+    fn negative_accounts() {
+        let mut store = AccountStore::default();
+        store.insert(Account::create(1, 10, 0, false));
+        store.insert(Account::create(2, -10, 30, true));
+        store.insert(Account::create(3, -10, 5, false));
+
+        assert_eq!(
+            store.find_negative_balance_accounts(),
+            vec![
+                &Account::create(2, -10, 30, true),
+                &Account::create(3, -10, 5, false)
+            ]
+        );
+        assert_eq!(
+            store.find_negative_total_accounts(),
+            vec![&Account::create(3, -10, 5, false)]
+        );
+    }
+
+    #[test]
+    fn extend() {
+        let mut store = AccountStore::default();
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+
+        let mut other = AccountStore::default();
+        other.get_mut(ClientId(2)).deposit(20).unwrap();
+        store.extend(other).unwrap();
+        assert_eq!(store.len(), 2);
+
+        let mut other = AccountStore::default();
+        other.get_mut(ClientId(1)).deposit(5).unwrap();
+        other.get_mut(ClientId(3)).deposit(5).unwrap();
+        assert_eq!(
+            store.extend(other),
+            Err(PaymentError::DuplicateAccount {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(store.get(ClientId(1)).map(Account::total), Some(10.into()));
+        assert!(!store.contains_client(ClientId(3)));
+    }
+
+    #[test]
+    fn merge() {
+        let existing = || {
+            let mut store = AccountStore::default();
+            store.insert(Account::create(1, 10, 5, false));
+            store.insert(Account::create(2, 20, 0, false));
+            store
+        };
+        let incoming = || {
+            let mut other = AccountStore::default();
+            other.insert(Account::create(2, 5, 1, true));
+            other.insert(Account::create(3, 30, 0, false));
+            other
+        };
+
+        let mut merged = existing();
+        merged.merge(incoming(), MergeStrategy::Overwrite).unwrap();
+        assert_eq!(
+            merged.get(ClientId(2)),
+            Some(&Account::create(2, 5, 1, true))
+        );
+        assert_eq!(merged.len(), 3);
+
+        let mut merged = existing();
+        merged
+            .merge(incoming(), MergeStrategy::KeepExisting)
+            .unwrap();
+        assert_eq!(
+            merged.get(ClientId(2)),
+            Some(&Account::create(2, 20, 0, false))
+        );
+        assert_eq!(merged.len(), 3);
+
+        let mut merged = existing();
+        merged
+            .merge(incoming(), MergeStrategy::SumBalances)
+            .unwrap();
+        assert_eq!(
+            merged.into_sorted_vec(),
+            vec![
+                Account::create(1, 10, 5, false),
+                Account::create(2, 25, 1, true),
+                Account::create(3, 30, 0, false),
+            ]
+        );
+
+        let mut merged = existing();
+        assert_eq!(
+            merged.merge(incoming(), MergeStrategy::Fail),
+            Err(PaymentError::DuplicateAccount {
+                client: ClientId(2)
+            })
+        );
+        assert_eq!(merged.into_sorted_vec(), existing().into_sorted_vec());
+
+        let mut merged = existing();
+        let mut other = AccountStore::default();
+        other.insert(Account::create(1, Decimal::MAX, 0, false));
+        other.insert(Account::create(3, 30, 0, false));
+        assert_eq!(
+            merged.merge(other, MergeStrategy::SumBalances),
+            Err(PaymentError::Merge(AccountError::Overflow {
+                client: ClientId(1)
+            }))
+        );
+        assert_eq!(
+            merged.get(ClientId(1)),
+            Some(&Account::create(1, 10, 5, false))
+        );
+        assert_eq!(merged.get(ClientId(3)), None);
+    }
+
+    #[test]
+    fn extend_from_iter() {
+        let mut store = AccountStore::default();
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+        store.get_mut(ClientId(2)).deposit(20).unwrap();
+
+        store.extend_from_iter(vec![
+            Account::create(2, 5, 0, false),
+            Account::create(3, 0, 0, true),
+        ]);
+
+        assert_eq!(
+            store.into_sorted_vec(),
+            vec![
+                Account::create(1, 10, 0, false),
+                Account::create(2, 5, 0, false),
+                Account::create(3, 0, 0, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn n_by_balance() {
+        let mut store = AccountStore::default();
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+        store.get_mut(ClientId(2)).deposit(30).unwrap();
+        store.get_mut(ClientId(3)).dispute(5).unwrap();
+        store.get_mut(ClientId(4)).deposit(10).unwrap();
+        store.get_mut(ClientId(5));
+
+        let clients = |accounts: Vec<(ClientId, &Account)>| {
+            accounts
+                .into_iter()
+                .map(|(client, _)| client.get())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(clients(store.top_n_by_balance(3)), vec![2, 1, 4]);
+        assert_eq!(clients(store.bottom_n_by_balance(2)), vec![3, 5]);
+        assert_eq!(clients(store.top_n_by_balance(10)), vec![2, 1, 4, 3, 5]);
+        assert!(store.top_n_by_balance(0).is_empty());
+    }
+
+    #[test]
+    fn sorted() {
+        let mut store = AccountStore::default();
+        for client in [3, 1, 4, 2] {
+            store.get_mut(ClientId(client)).deposit(client).unwrap();
+        }
+
+        assert_eq!(
+            store
+                .sorted_iter()
+                .map(|acc| acc.client().get())
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(
+            store.into_sorted_vec(),
+            (1..=4)
+                .map(|client| Account::create(client, client, 0, false))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn partition_by_locked() {
+        let mut store = AccountStore::default();
+        store.get_mut(ClientId(1)).deposit(10).unwrap();
+        store.get_mut(ClientId(2)).chargeback(0).unwrap();
+        store.get_mut(ClientId(3)).chargeback(0).unwrap();
+
+        let (locked, unlocked) = store.split_by_locked();
+        assert_eq!(locked.len(), 2);
+        assert!(locked.iter().all(|acc| acc.is_locked()));
+        assert_eq!(unlocked, vec![&Account::create(1, 10, 0, false)]);
+
+        let (locked, unlocked) = store.partition_by_locked();
+        assert_eq!(locked.len(), 2);
+        assert!(locked.contains_client(ClientId(2)));
+        assert!(locked.contains_client(ClientId(3)));
+        assert_eq!(unlocked.len(), 1);
+        assert!(unlocked.contains_client(ClientId(1)));
     }
 }
@@ -5,12 +5,44 @@ pub mod operation;
 
 mod payment;
 
-// helper function to ensure we always have the same configuration for the csv reader
+/// Settings of the CSV reader. The default reads comma separated files with
+/// a header row, trims all fields and allows rows with a missing amount column.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvConfig {
+    pub delimiter: u8,
+    /// `csv::Trim::None` keeps the whitespace around fields, inputs with
+    /// spaces after the delimiters, e.g. `deposit, 1, 1, 10`, then fail to
+    /// parse as the ids and amounts can't contain whitespace.
+    pub trim: csv::Trim,
+    pub has_headers: bool,
+    pub flexible: bool,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            trim: csv::Trim::All,
+            has_headers: true,
+            flexible: true,
+        }
+    }
+}
+
+/// Reader builder with the default `CsvConfig`, used wherever CSV is read to
+/// ensure the same configuration. All fields are trimmed because inputs
+/// usually contain spaces after the commas, e.g. `deposit, 1, 1, 10`, which
+/// the deserialization of the ids and amounts would reject otherwise.
 pub fn csv_reader_builder() -> csv::ReaderBuilder {
+    csv_reader_builder_with_config(&CsvConfig::default())
+}
+
+pub fn csv_reader_builder_with_config(config: &CsvConfig) -> csv::ReaderBuilder {
     let mut builder = csv::ReaderBuilder::new();
     builder
-        .trim(csv::Trim::All)
-        .has_headers(true)
-        .flexible(true);
+        .delimiter(config.delimiter)
+        .trim(config.trim)
+        .has_headers(config.has_headers)
+        .flexible(config.flexible);
     builder
 }
@@ -9,7 +9,7 @@ fn main() -> anyhow::Result<()> {
         )
     }
 
-    let accounts = PaymentEngine::process_csv(&args[1])?;
+    let (accounts, _) = PaymentEngine::process_csv(&args[1])?;
 
     let mut writer = csv::Writer::from_writer(std::io::stdout());
     for (_, account) in accounts.into_iter() {
@@ -1,18 +1,72 @@
+pub use bounded_transaction_store::*;
 pub use error::*;
 pub use transaction_store::*;
 
+#[cfg(test)]
+pub(crate) mod arbitrary;
+mod bounded_transaction_store;
 mod error;
 mod transaction_store;
 
+use std::fmt;
+
 use derive_more::From;
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::account::ClientId;
 
-pub type TransactionId = u32;
+/// Id of a transaction. Valid ids start at `1`, `0` is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct TransactionId(pub(crate) u64);
+
+impl TransactionId {
+    pub const MAX_VALUE: TransactionId = TransactionId(u64::MAX);
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
 
-#[derive(Deserialize, Debug, PartialEq, Eq, From)]
+impl TryFrom<u64> for TransactionId {
+    type Error = TransactionError;
+
+    fn try_from(id: u64) -> Result<Self, Self::Error> {
+        if id == 0 {
+            return Err(TransactionError::InvalidTransactionId { id });
+        }
+
+        Ok(TransactionId(id))
+    }
+}
+
+impl From<TransactionId> for u64 {
+    fn from(tx: TransactionId) -> Self {
+        tx.0
+    }
+}
+
+impl fmt::Display for TransactionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// maximal number of decimal places of transaction amounts
+const MAX_AMOUNT_SCALE: u32 = 4;
+
+/// Implemented by all types which reference a transaction.
+pub trait HasTransactionId {
+    fn transaction_id(&self) -> TransactionId;
+}
+
+/// Implemented by all types which belong to a client.
+pub trait HasClientId {
+    fn client(&self) -> ClientId;
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, From)]
 #[serde(try_from = "OperationDto")]
 pub enum Operation {
     Transaction(Transaction),
@@ -20,21 +74,104 @@ pub enum Operation {
 }
 
 impl Operation {
+    /// Deserializes a single operation from a JSON object with
+    /// the same fields as the CSV columns, e.g.
+    /// `{"type": "deposit", "client": 1, "tx": 1, "amount": "10.5"}`.
+    pub fn from_json_str(s: &str) -> Result<Operation, serde_json::Error> {
+        serde_json::from_str(s)
+    }
+
+    /// Creates an operation from the values of the CSV columns, applying
+    /// the same validation as the deserialization.
+    pub fn from_row(
+        type_: &str,
+        client: ClientId,
+        tx: TransactionId,
+        amount: Option<Decimal>,
+    ) -> Result<Operation, TransactionError> {
+        Operation::try_from(OperationDto {
+            type_: type_.to_string(),
+            client,
+            tx,
+            amount,
+        })
+    }
+
+    /// Applies the same checks as the deserialization, e.g. to validate
+    /// operations that were created programmatically. Conflicts are
+    /// always valid.
+    pub fn validate(&self) -> Result<(), TransactionError> {
+        match self {
+            Operation::Transaction(tx) => tx.validate(),
+            Operation::Conflict(_) => Ok(()),
+        }
+    }
+
     pub fn client(&self) -> ClientId {
         match self {
             Operation::Transaction(tx) => tx.client,
             Operation::Conflict(dm) => dm.client,
         }
     }
+
+    pub fn transaction_id(&self) -> TransactionId {
+        match self {
+            Operation::Transaction(tx) => tx.transaction_id(),
+            Operation::Conflict(dm) => dm.transaction_id(),
+        }
+    }
+
+    /// The amount of a transaction, conflicts have no amount.
+    pub fn amount(&self) -> Option<Decimal> {
+        match self {
+            Operation::Transaction(tx) => Some(tx.amount),
+            Operation::Conflict(_) => None,
+        }
+    }
+
+    pub fn is_transaction(&self) -> bool {
+        matches!(self, Operation::Transaction(_))
+    }
+
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, Operation::Conflict(_))
+    }
+
+    pub fn as_transaction(&self) -> Option<&Transaction> {
+        match self {
+            Operation::Transaction(tx) => Some(tx),
+            Operation::Conflict(_) => None,
+        }
+    }
+
+    pub fn as_conflict(&self) -> Option<&Conflict> {
+        match self {
+            Operation::Transaction(_) => None,
+            Operation::Conflict(dm) => Some(dm),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
     Withdrawal,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl TransactionType {
+    /// The name used for the `type` column of the CSV input.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+// `Decimal` normalizes its value before hashing, so amounts which only
+// differ in their scale, e.g. `10` and `10.00`, have the same hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Transaction {
     pub type_: TransactionType,
     pub client: ClientId,
@@ -43,6 +180,18 @@ pub struct Transaction {
 }
 
 impl Transaction {
+    /// Checks the amount, see `Operation::validate`.
+    pub fn validate(&self) -> Result<(), TransactionError> {
+        check_amount(self.amount).map_err(|reason| TransactionError::InvalidAmount {
+            id: self.tx,
+            reason,
+        })
+    }
+
+    pub fn transaction_id(&self) -> TransactionId {
+        self.tx
+    }
+
     #[allow(unused)]
     pub fn deposit(client: ClientId, tx: TransactionId, amount: impl Into<Decimal>) -> Self {
         Transaction {
@@ -64,14 +213,32 @@ impl Transaction {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ConflictType {
     Dispute,
     Resolve,
     Chargeback,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl ConflictType {
+    /// The name used for the `type` column of the CSV input.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictType::Dispute => "dispute",
+            ConflictType::Resolve => "resolve",
+            ConflictType::Chargeback => "chargeback",
+        }
+    }
+
+    /// Whether the conflict closes the disputed transaction for good, which
+    /// is only the case for a chargeback. A resolved transaction can be
+    /// disputed again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, ConflictType::Chargeback)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Conflict {
     pub type_: ConflictType,
     pub client: ClientId,
@@ -79,6 +246,10 @@ pub struct Conflict {
 }
 
 impl Conflict {
+    pub fn transaction_id(&self) -> TransactionId {
+        self.tx
+    }
+
     #[allow(unused)]
     pub fn dispute(client: ClientId, tx: TransactionId) -> Self {
         Conflict {
@@ -107,6 +278,193 @@ impl Conflict {
     }
 }
 
+impl HasTransactionId for Operation {
+    fn transaction_id(&self) -> TransactionId {
+        Operation::transaction_id(self)
+    }
+}
+
+impl HasTransactionId for Transaction {
+    fn transaction_id(&self) -> TransactionId {
+        Transaction::transaction_id(self)
+    }
+}
+
+impl HasTransactionId for Conflict {
+    fn transaction_id(&self) -> TransactionId {
+        Conflict::transaction_id(self)
+    }
+}
+
+impl HasClientId for Operation {
+    fn client(&self) -> ClientId {
+        Operation::client(self)
+    }
+}
+
+impl HasClientId for Transaction {
+    fn client(&self) -> ClientId {
+        self.client
+    }
+}
+
+impl HasClientId for Conflict {
+    fn client(&self) -> ClientId {
+        self.client
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Transaction(tx) => tx.fmt(f),
+            Operation::Conflict(dm) => dm.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[client={}, tx={}, amount={}]",
+            self.type_, self.client, self.tx, self.amount
+        )
+    }
+}
+
+impl fmt::Display for ConflictType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[client={}, tx={}]", self.type_, self.client, self.tx)
+    }
+}
+
+// Operations are converted from and to single CSV rows in the column order
+// `type,client,tx,amount`, conflicts omit the amount column.
+impl From<Transaction> for String {
+    fn from(tx: Transaction) -> Self {
+        format!("{},{},{},{}", tx.type_, tx.client, tx.tx, tx.amount)
+    }
+}
+
+impl From<Conflict> for String {
+    fn from(conflict: Conflict) -> Self {
+        format!("{},{},{}", conflict.type_, conflict.client, conflict.tx)
+    }
+}
+
+impl From<Operation> for String {
+    fn from(operation: Operation) -> Self {
+        match operation {
+            Operation::Transaction(tx) => tx.into(),
+            Operation::Conflict(dm) => dm.into(),
+        }
+    }
+}
+
+impl TryFrom<&str> for Operation {
+    type Error = TransactionError;
+
+    fn try_from(row: &str) -> Result<Self, Self::Error> {
+        let malformed = || TransactionError::MalformedRow {
+            row: row.to_string(),
+        };
+
+        let mut columns = row.split(',').map(str::trim);
+        let type_ = columns.next().ok_or_else(malformed)?;
+        let client = columns
+            .next()
+            .and_then(|client| client.parse::<u32>().ok())
+            .and_then(|client| ClientId::try_from(client).ok())
+            .ok_or_else(malformed)?;
+        let tx = columns
+            .next()
+            .and_then(|tx| tx.parse::<u64>().ok())
+            .and_then(|tx| TransactionId::try_from(tx).ok())
+            .ok_or_else(malformed)?;
+        let amount = match columns.next() {
+            None | Some("") => None,
+            Some(amount) => Some(amount.parse().map_err(|_| malformed())?),
+        };
+        if columns.next().is_some() {
+            return Err(malformed());
+        }
+
+        Operation::from_row(type_, client, tx, amount)
+    }
+}
+
+impl TryFrom<&str> for Transaction {
+    type Error = TransactionError;
+
+    fn try_from(row: &str) -> Result<Self, Self::Error> {
+        match Operation::try_from(row)? {
+            Operation::Transaction(tx) => Ok(tx),
+            Operation::Conflict(_) => Err(TransactionError::MalformedRow {
+                row: row.to_string(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<&str> for Conflict {
+    type Error = TransactionError;
+
+    fn try_from(row: &str) -> Result<Self, Self::Error> {
+        match Operation::try_from(row)? {
+            Operation::Transaction(_) => Err(TransactionError::MalformedRow {
+                row: row.to_string(),
+            }),
+            Operation::Conflict(dm) => Ok(dm),
+        }
+    }
+}
+
+// All checks a transaction amount has to pass.
+fn check_amount(amount: Decimal) -> Result<(), AmountRejectionReason> {
+    // A negative deposit would effectively be a withdrawal which
+    // bypasses the available funds check of the account.
+    if amount < Decimal::ZERO {
+        return Err(AmountRejectionReason::Negative);
+    }
+
+    // A transaction without an amount doesn't change the account
+    // but would still occupy the transaction id.
+    if amount.is_zero() {
+        return Err(AmountRejectionReason::Zero);
+    }
+
+    // The decimal places are counted without trailing zeros as they don't
+    // add any precision, therefore `1.00000` is accepted as `1` while
+    // `1.00005` is rejected.
+    let scale = amount.normalize().scale();
+    if scale > MAX_AMOUNT_SCALE {
+        return Err(AmountRejectionReason::ExcessPrecision { scale });
+    }
+
+    // Balances are kept with four decimal places, so amounts which can't
+    // be represented with them, i.e. close to `Decimal::MAX`, are rejected.
+    let mut rescaled = amount;
+    rescaled.rescale(MAX_AMOUNT_SCALE);
+    if rescaled.scale() != MAX_AMOUNT_SCALE {
+        return Err(AmountRejectionReason::Overflow);
+    }
+
+    Ok(())
+}
+
 #[derive(Deserialize)]
 pub struct OperationDto {
     #[serde(rename = "type")]
@@ -116,32 +474,34 @@ pub struct OperationDto {
     amount: Option<Decimal>,
 }
 
+impl OperationDto {
+    fn transaction_amount(&self) -> TransactionResult<Decimal> {
+        self.amount
+            .ok_or_else(|| TransactionError::DeserializeMissingAmount {
+                type_: self.type_.clone(),
+                id: self.tx,
+            })
+    }
+}
+
 impl TryFrom<OperationDto> for Operation {
     type Error = TransactionError;
 
     fn try_from(dto: OperationDto) -> Result<Self, Self::Error> {
-        match dto.type_.as_str() {
+        // third party exports often use mixed case type names, the type is
+        // trimmed as well since JSON input isn't trimmed by the CSV reader
+        let operation = match dto.type_.trim().to_ascii_lowercase().as_str() {
             "deposit" => Ok(Operation::Transaction(Transaction {
                 type_: TransactionType::Deposit,
                 tx: dto.tx,
                 client: dto.client,
-                amount: dto
-                    .amount
-                    .ok_or(TransactionError::DeserializeMissingAmount {
-                        type_: dto.type_,
-                        id: dto.tx,
-                    })?,
+                amount: dto.transaction_amount()?,
             })),
             "withdrawal" => Ok(Operation::Transaction(Transaction {
                 type_: TransactionType::Withdrawal,
                 tx: dto.tx,
                 client: dto.client,
-                amount: dto
-                    .amount
-                    .ok_or(TransactionError::DeserializeMissingAmount {
-                        type_: dto.type_,
-                        id: dto.tx,
-                    })?,
+                amount: dto.transaction_amount()?,
             })),
             "dispute" => Ok(Operation::Conflict(Conflict {
                 type_: ConflictType::Dispute,
@@ -162,12 +522,17 @@ impl TryFrom<OperationDto> for Operation {
                 type_: dto.type_,
                 id: dto.tx,
             }),
-        }
+        }?;
+
+        operation.validate()?;
+        Ok(operation)
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+
     use itertools::Itertools;
 
     use crate::csv_reader_builder;
@@ -194,12 +559,492 @@ chargeback, 1, 1
         assert_eq!(
             operations,
             vec![
-                Operation::from(Transaction::deposit(1, 1, 10)),
-                Operation::from(Transaction::withdrawal(1, 2, 5)),
-                Operation::from(Conflict::dispute(1, 1)),
-                Operation::from(Conflict::resolve(1, 1)),
-                Operation::from(Conflict::chargeback(1, 1)),
+                Operation::from(Transaction::deposit(ClientId(1), TransactionId(1), 10)),
+                Operation::from(Transaction::withdrawal(ClientId(1), TransactionId(2), 5)),
+                Operation::from(Conflict::dispute(ClientId(1), TransactionId(1))),
+                Operation::from(Conflict::resolve(ClientId(1), TransactionId(1))),
+                Operation::from(Conflict::chargeback(ClientId(1), TransactionId(1))),
             ]
         );
     }
+
+    #[test]
+    fn from_json() {
+        assert_eq!(
+            Operation::from_json_str(r#"{"type": "deposit", "client": 1, "tx": 1, "amount": 10}"#)
+                .unwrap(),
+            Operation::from(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+        );
+        assert_eq!(
+            Operation::from_json_str(
+                r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": "2.5"}"#
+            )
+            .unwrap(),
+            Operation::from(Transaction::withdrawal(
+                ClientId(1),
+                TransactionId(2),
+                Decimal::new(25, 1)
+            ))
+        );
+        assert_eq!(
+            Operation::from_json_str(r#"{"type": "dispute", "client": 1, "tx": 1}"#).unwrap(),
+            Operation::from(Conflict::dispute(ClientId(1), TransactionId(1)))
+        );
+
+        assert!(Operation::from_json_str(r#"{"type": "deposit", "client": 1, "tx": 1}"#).is_err());
+        assert!(Operation::from_json_str(r#"{"type": "unknown", "client": 1, "tx": 1}"#).is_err());
+    }
+
+    #[test]
+    fn csv_row() {
+        let operations: [Operation; 3] = [
+            Transaction::deposit(ClientId(1), TransactionId(2), Decimal::new(105, 1)).into(),
+            Transaction::withdrawal(ClientId(1), TransactionId(3), 5).into(),
+            Conflict::resolve(ClientId(1), TransactionId(2)).into(),
+        ];
+        let rows = operations.map(String::from);
+        assert_eq!(
+            rows,
+            ["deposit,1,2,10.5", "withdrawal,1,3,5", "resolve,1,2"]
+        );
+
+        for (row, operation) in rows.iter().zip(operations) {
+            assert_eq!(Operation::try_from(row.as_str()), Ok(operation));
+        }
+
+        assert_eq!(
+            Transaction::try_from(" deposit, 1, 2, 10.5 "),
+            Ok(Transaction::deposit(
+                ClientId(1),
+                TransactionId(2),
+                Decimal::new(105, 1)
+            ))
+        );
+        assert_eq!(
+            Conflict::try_from("chargeback, 1, 2,"),
+            Ok(Conflict::chargeback(ClientId(1), TransactionId(2)))
+        );
+        for row in ["dispute,1,2", "deposit,x,2,1", "deposit,1,2,1,1", "deposit"] {
+            assert_eq!(
+                Transaction::try_from(row),
+                Err(TransactionError::MalformedRow {
+                    row: row.to_string()
+                })
+            );
+        }
+        assert_eq!(
+            Transaction::try_from("deposit,1,2,-1"),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(2),
+                reason: AmountRejectionReason::Negative
+            })
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(
+            Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(1),
+                Decimal::new(105, 1)
+            ))
+            .validate(),
+            Ok(())
+        );
+        assert_eq!(
+            Operation::from(Transaction::withdrawal(ClientId(1), TransactionId(2), -1)).validate(),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(2),
+                reason: AmountRejectionReason::Negative
+            })
+        );
+        assert_eq!(
+            Transaction::deposit(ClientId(1), TransactionId(3), 0).validate(),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(3),
+                reason: AmountRejectionReason::Zero
+            })
+        );
+        assert_eq!(
+            Transaction::deposit(ClientId(1), TransactionId(4), Decimal::MAX).validate(),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(4),
+                reason: AmountRejectionReason::Overflow
+            })
+        );
+        // the largest amount with four decimal places
+        assert_eq!(
+            Transaction::deposit(
+                ClientId(1),
+                TransactionId(5),
+                Decimal::from_i128_with_scale(Decimal::MAX.mantissa(), 4)
+            )
+            .validate(),
+            Ok(())
+        );
+        assert_eq!(
+            Operation::from(Conflict::dispute(ClientId(1), TransactionId(2))).validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn from_row() {
+        assert_eq!(
+            Operation::from_row("deposit", ClientId(1), TransactionId(2), Some(10.into())),
+            Ok(Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(2),
+                10
+            )))
+        );
+        assert_eq!(
+            Operation::from_row("chargeback", ClientId(1), TransactionId(2), None),
+            Ok(Operation::from(Conflict::chargeback(
+                ClientId(1),
+                TransactionId(2)
+            )))
+        );
+        assert_eq!(
+            Operation::from_row("withdrawal", ClientId(1), TransactionId(2), None),
+            Err(TransactionError::DeserializeMissingAmount {
+                type_: "withdrawal".to_string(),
+                id: TransactionId(2)
+            })
+        );
+        assert_eq!(
+            Operation::from_row("transfer", ClientId(1), TransactionId(2), Some(10.into())),
+            Err(TransactionError::DeserializeUnknownType {
+                type_: "transfer".to_string(),
+                id: TransactionId(2)
+            })
+        );
+    }
+
+    #[test]
+    fn transaction_id() {
+        assert_eq!(
+            TransactionId::try_from(0),
+            Err(TransactionError::InvalidTransactionId { id: 0 })
+        );
+        assert_eq!(TransactionId::try_from(1).map(TransactionId::get), Ok(1));
+        assert_eq!(TransactionId::MAX_VALUE.get(), u64::MAX);
+
+        assert!(Operation::try_from("deposit, 1, 0, 10").is_err());
+        assert_eq!(
+            Operation::try_from("deposit, 1, 5000000000, 10"),
+            Ok(Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(5_000_000_000),
+                10
+            )))
+        );
+        assert!(Operation::from_json_str(r#"{"type": "dispute", "client": 1, "tx": 0}"#).is_err());
+    }
+
+    #[test]
+    fn case_insensitive_type() {
+        assert_eq!(
+            Operation::from_row("Deposit", ClientId(1), TransactionId(2), Some(10.into())),
+            Ok(Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(2),
+                10
+            )))
+        );
+        assert_eq!(
+            Operation::try_from("WITHDRAWAL, 1, 3, 5"),
+            Ok(Operation::from(Transaction::withdrawal(
+                ClientId(1),
+                TransactionId(3),
+                5
+            )))
+        );
+        assert_eq!(
+            Operation::from_json_str(r#"{"type": "ChargeBack", "client": 1, "tx": 2}"#).ok(),
+            Some(Operation::from(Conflict::chargeback(
+                ClientId(1),
+                TransactionId(2)
+            )))
+        );
+    }
+
+    #[test]
+    fn trimmed_type() {
+        assert_eq!(
+            Operation::from_row(" deposit\t", ClientId(1), TransactionId(2), Some(10.into())),
+            Ok(Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(2),
+                10
+            )))
+        );
+        assert_eq!(
+            Operation::from_json_str(r#"{"type": " dispute ", "client": 1, "tx": 2}"#).ok(),
+            Some(Operation::from(Conflict::dispute(
+                ClientId(1),
+                TransactionId(2)
+            )))
+        );
+    }
+
+    #[test]
+    fn dedup() {
+        let operations: HashSet<Operation> = [
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Transaction::deposit(ClientId(1), TransactionId(1), Decimal::new(1000, 2)).into(),
+            Transaction::withdrawal(ClientId(1), TransactionId(1), 10).into(),
+            Conflict::dispute(ClientId(1), TransactionId(1)).into(),
+            Conflict::dispute(ClientId(1), TransactionId(1)).into(),
+            Conflict::resolve(ClientId(1), TransactionId(1)).into(),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(operations.len(), 4);
+    }
+
+    #[test]
+    fn as_str() {
+        for type_ in [TransactionType::Deposit, TransactionType::Withdrawal] {
+            let operation = Operation::from_row(
+                type_.as_str(),
+                ClientId(1),
+                TransactionId(2),
+                Some(10.into()),
+            );
+            assert_eq!(
+                operation.map(|operation| operation.as_transaction().map(|tx| tx.type_)),
+                Ok(Some(type_))
+            );
+        }
+
+        for type_ in [
+            ConflictType::Dispute,
+            ConflictType::Resolve,
+            ConflictType::Chargeback,
+        ] {
+            let operation =
+                Operation::from_row(type_.as_str(), ClientId(1), TransactionId(2), None);
+            assert_eq!(
+                operation.map(|operation| operation.as_conflict().map(|conflict| conflict.type_)),
+                Ok(Some(type_))
+            );
+        }
+    }
+
+    #[test]
+    fn is_terminal() {
+        assert!(!ConflictType::Dispute.is_terminal());
+        assert!(!ConflictType::Resolve.is_terminal());
+        assert!(ConflictType::Chargeback.is_terminal());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(2),
+                Decimal::new(105, 1)
+            ))
+            .to_string(),
+            "deposit[client=1, tx=2, amount=10.5]"
+        );
+        assert_eq!(
+            Operation::from(Transaction::withdrawal(ClientId(1), TransactionId(3), 5)).to_string(),
+            "withdrawal[client=1, tx=3, amount=5]"
+        );
+        assert_eq!(
+            Operation::from(Conflict::chargeback(ClientId(1), TransactionId(2))).to_string(),
+            "chargeback[client=1, tx=2]"
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        let tx = Operation::from(Transaction::deposit(ClientId(1), TransactionId(2), 10));
+        assert_eq!(tx.client(), ClientId(1));
+        assert_eq!(tx.transaction_id(), TransactionId(2));
+        assert_eq!(tx.amount(), Some(10.into()));
+        assert!(tx.is_transaction());
+        assert!(!tx.is_conflict());
+        assert_eq!(
+            tx.as_transaction(),
+            Some(&Transaction::deposit(ClientId(1), TransactionId(2), 10))
+        );
+        assert_eq!(tx.as_conflict(), None);
+
+        let dm = Operation::from(Conflict::dispute(ClientId(3), TransactionId(4)));
+        assert_eq!(dm.client(), ClientId(3));
+        assert_eq!(dm.transaction_id(), TransactionId(4));
+        assert_eq!(dm.amount(), None);
+        assert!(!dm.is_transaction());
+        assert!(dm.is_conflict());
+        assert_eq!(dm.as_transaction(), None);
+        assert_eq!(
+            dm.as_conflict(),
+            Some(&Conflict::dispute(ClientId(3), TransactionId(4)))
+        );
+
+        fn ids(items: &[&dyn HasTransactionId]) -> Vec<TransactionId> {
+            items.iter().map(|item| item.transaction_id()).collect()
+        }
+        assert_eq!(
+            ids(&[
+                &Transaction::withdrawal(ClientId(1), TransactionId(5), 10),
+                &Conflict::resolve(ClientId(1), TransactionId(6)),
+                &tx,
+            ]),
+            vec![TransactionId(5), TransactionId(6), TransactionId(2)]
+        );
+    }
+
+    #[test]
+    fn has_ids() {
+        fn find_by_tx<T: HasTransactionId>(items: &[T], id: TransactionId) -> Option<&T> {
+            items.iter().find(|item| item.transaction_id() == id)
+        }
+
+        let conflicts = [
+            Conflict::dispute(ClientId(1), TransactionId(1)),
+            Conflict::resolve(ClientId(2), TransactionId(2)),
+        ];
+        assert_eq!(
+            find_by_tx(&conflicts, TransactionId(2)).map(HasClientId::client),
+            Some(ClientId(2))
+        );
+        assert_eq!(find_by_tx(&conflicts, TransactionId(3)), None);
+
+        let value =
+            TransactionStoreValue::new(Transaction::deposit(ClientId(3), TransactionId(4), 10));
+        assert_eq!(value.transaction_id(), TransactionId(4));
+        assert_eq!(HasClientId::client(&value), ClientId(3));
+    }
+
+    #[test]
+    fn negative_amount() {
+        let dto = OperationDto {
+            type_: "deposit".to_string(),
+            client: ClientId(1),
+            tx: TransactionId(1),
+            amount: Some((-100).into()),
+        };
+
+        assert_eq!(
+            Operation::try_from(dto),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(1),
+                reason: AmountRejectionReason::Negative
+            })
+        );
+
+        let data = "\
+type, client, tx, amount
+deposit, 1, 1, -10
+withdrawal, 1, 2, -5
+deposit, 1, 3, 10
+";
+
+        let operations = csv_reader_builder()
+            .from_reader(data.as_bytes())
+            .into_deserialize::<Operation>()
+            .filter_map(|res| res.ok())
+            .collect_vec();
+
+        assert_eq!(
+            operations,
+            vec![Operation::from(Transaction::deposit(
+                ClientId(1),
+                TransactionId(3),
+                10
+            ))]
+        );
+    }
+
+    #[test]
+    fn zero_amount() {
+        for (type_, amount) in [
+            ("deposit", Decimal::ZERO),
+            ("withdrawal", Decimal::new(0, 4)),
+            ("deposit", -Decimal::ZERO),
+        ] {
+            let dto = OperationDto {
+                type_: type_.to_string(),
+                client: ClientId(1),
+                tx: TransactionId(1),
+                amount: Some(amount),
+            };
+
+            assert_eq!(
+                Operation::try_from(dto),
+                Err(TransactionError::InvalidAmount {
+                    id: TransactionId(1),
+                    reason: AmountRejectionReason::Zero
+                })
+            );
+        }
+
+        // conflicts have no amount and are therefore unaffected
+        let dto = OperationDto {
+            type_: "dispute".to_string(),
+            client: ClientId(1),
+            tx: TransactionId(1),
+            amount: None,
+        };
+        assert_eq!(
+            Operation::try_from(dto),
+            Ok(Operation::from(Conflict::dispute(
+                ClientId(1),
+                TransactionId(1)
+            )))
+        );
+    }
+
+    #[test]
+    fn excess_precision() {
+        let data = "\
+type, client, tx, amount
+deposit, 1, 1, 1.000050000
+deposit, 1, 2, 1.00000
+deposit, 1, 3, 1.0001
+";
+
+        let operations = csv_reader_builder()
+            .from_reader(data.as_bytes())
+            .into_deserialize::<Operation>()
+            .filter_map(|res| res.ok())
+            .collect_vec();
+
+        assert_eq!(
+            operations,
+            vec![
+                Operation::from(Transaction::deposit(
+                    ClientId(1),
+                    TransactionId(2),
+                    Decimal::new(100000, 5)
+                )),
+                Operation::from(Transaction::deposit(
+                    ClientId(1),
+                    TransactionId(3),
+                    Decimal::new(10001, 4)
+                )),
+            ]
+        );
+
+        let dto = OperationDto {
+            type_: "withdrawal".to_string(),
+            client: ClientId(1),
+            tx: TransactionId(1),
+            amount: Some(Decimal::new(1000050000, 9)),
+        };
+        assert_eq!(
+            Operation::try_from(dto),
+            Err(TransactionError::InvalidAmount {
+                id: TransactionId(1),
+                reason: AmountRejectionReason::ExcessPrecision { scale: 5 }
+            })
+        );
+    }
 }
@@ -0,0 +1,58 @@
+use proptest::{arbitrary::Arbitrary, prelude::*, strategy::BoxedStrategy};
+use rust_decimal::Decimal;
+
+use crate::{
+    account::ClientId,
+    operation::{Conflict, ConflictType, Operation, Transaction, TransactionId, TransactionType},
+};
+
+/// Positive amounts with up to four decimal places.
+pub(crate) fn amount() -> impl Strategy<Value = Decimal> {
+    (1..1_000_000_000_000i64, 0..=4u32).prop_map(|(num, scale)| Decimal::new(num, scale))
+}
+
+// Ids are drawn from a small range so that conflicts
+// reference previously generated transactions.
+impl Arbitrary for TransactionId {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        (1..=100u64).prop_map(TransactionId).boxed()
+    }
+}
+
+impl Arbitrary for Operation {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        let transaction_type = prop_oneof![
+            Just(TransactionType::Deposit),
+            Just(TransactionType::Withdrawal),
+        ];
+        let conflict_type = prop_oneof![
+            Just(ConflictType::Dispute),
+            Just(ConflictType::Resolve),
+            Just(ConflictType::Chargeback),
+        ];
+
+        prop_oneof![
+            (
+                transaction_type,
+                any::<ClientId>(),
+                any::<TransactionId>(),
+                amount()
+            )
+                .prop_map(|(type_, client, tx, amount)| Operation::from(Transaction {
+                    type_,
+                    client,
+                    tx,
+                    amount,
+                })),
+            (conflict_type, any::<ClientId>(), any::<TransactionId>())
+                .prop_map(|(type_, client, tx)| Operation::from(Conflict { type_, client, tx })),
+        ]
+        .boxed()
+    }
+}
@@ -0,0 +1,243 @@
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+
+use crate::operation::{
+    DisputeState, Transaction, TransactionError, TransactionId, TransactionResult,
+    TransactionStoreValue, TransactionStoreValueMut,
+};
+
+/// A `TransactionStore` which holds at most `max_entries` transactions.
+///
+/// When the store is full the least recently used transaction which is not
+/// currently disputed is evicted to make room for a new one. Disputed
+/// transactions are never evicted, so if all stored transactions are
+/// disputed new transactions are rejected with `CapacityExceeded`.
+#[derive(Clone)]
+pub struct BoundedTransactionStore(LruCache<TransactionId, TransactionStoreValue>);
+
+impl BoundedTransactionStore {
+    pub fn new(max_entries: NonZeroUsize) -> Self {
+        Self(LruCache::new(max_entries))
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.0.cap().get()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Approximate heap usage of the store in bytes. The cache allocates
+    /// each entry on its own with two list pointers and indexes it with a
+    /// key and entry pointer, so the estimate is based on the stored ones.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        self.0.len()
+            * (size_of::<(TransactionId, TransactionStoreValue)>() + 4 * size_of::<usize>() + 1)
+    }
+
+    /// Removes all transactions, the capacity is kept.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn contains(&self, id: TransactionId) -> bool {
+        self.0.contains(&id)
+    }
+
+    /// Returns `None` if the transaction is unknown.
+    pub fn is_disputed(&self, id: TransactionId) -> Option<bool> {
+        self.0
+            .peek(&id)
+            .map(|value| value.dispute_state == DisputeState::Disputed)
+    }
+
+    /// Iterates from the most to the least recently used transaction.
+    pub fn iter(&self) -> impl Iterator<Item = (&TransactionId, &TransactionStoreValue)> {
+        self.0.iter()
+    }
+
+    pub fn iter_disputed(&self) -> impl Iterator<Item = (&TransactionId, &TransactionStoreValue)> {
+        self.iter()
+            .filter(|(_, value)| value.dispute_state == DisputeState::Disputed)
+    }
+
+    /// Marks the transaction as most recently used.
+    pub fn get_mut(
+        &mut self,
+        id: TransactionId,
+    ) -> TransactionResult<TransactionStoreValueMut<'_>> {
+        self.0
+            .get_mut(&id)
+            .map(TransactionStoreValueMut::from)
+            .ok_or(TransactionError::NotFound { id })
+    }
+
+    // currently only used within tests
+    #[allow(unused)]
+    pub fn insert(&mut self, tx: Transaction) -> TransactionResult<()> {
+        self.lock_for_insert(tx)?.finish();
+        Ok(())
+    }
+
+    /// Same as `TransactionStore::lock_for_insert`, additionally fails with
+    /// `CapacityExceeded` if the store is full and no transaction can be
+    /// evicted.
+    pub fn lock_for_insert(
+        &mut self,
+        tx: Transaction,
+    ) -> TransactionResult<BoundedLockForInsert<'_>> {
+        if let Some(existing) = self.0.peek(&tx.tx) {
+            if existing.transaction.client != tx.client {
+                return Err(TransactionError::DuplicateClientId {
+                    id: tx.tx,
+                    original_client: existing.transaction.client,
+                    new_client: tx.client,
+                });
+            }
+
+            return Err(TransactionError::Conflict { id: tx.tx });
+        }
+
+        let evict = if self.0.len() < self.max_entries() {
+            None
+        } else {
+            // the iterator starts with the most recently used transaction
+            let candidate = self
+                .0
+                .iter()
+                .rev()
+                .find(|(_, value)| value.dispute_state != DisputeState::Disputed)
+                .map(|(id, _)| *id);
+            match candidate {
+                Some(id) => Some(id),
+                None => return Err(TransactionError::CapacityExceeded { id: tx.tx }),
+            }
+        };
+
+        Ok(BoundedLockForInsert {
+            store: &mut self.0,
+            evict,
+            value: TransactionStoreValue::new(tx),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundedLockForInsert<'a> {
+    store: &'a mut LruCache<TransactionId, TransactionStoreValue>,
+    evict: Option<TransactionId>,
+    value: TransactionStoreValue,
+}
+
+impl BoundedLockForInsert<'_> {
+    pub fn finish(self) {
+        if let Some(id) = self.evict {
+            self.store.pop(&id);
+        }
+
+        self.store.put(self.value.transaction.tx, self.value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::account::ClientId;
+
+    use super::*;
+
+    fn store(max_entries: usize) -> BoundedTransactionStore {
+        BoundedTransactionStore::new(NonZeroUsize::new(max_entries).unwrap())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut store = store(2);
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(2), 1))
+            .unwrap();
+
+        // using the first transaction makes the second one the oldest
+        store.get_mut(TransactionId(1)).unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(3), 1))
+            .unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(TransactionId(1)));
+        assert!(!store.contains(TransactionId(2)));
+        assert!(store.contains(TransactionId(3)));
+    }
+
+    #[test]
+    fn skips_disputed() {
+        let mut store = store(2);
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(2), 1))
+            .unwrap();
+        *store.get_mut(TransactionId(1)).unwrap().dispute_state = DisputeState::Disputed;
+        store.get_mut(TransactionId(2)).unwrap();
+
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(3), 1))
+            .unwrap();
+        assert!(store.contains(TransactionId(1)));
+        assert!(!store.contains(TransactionId(2)));
+        assert!(store.contains(TransactionId(3)));
+
+        *store.get_mut(TransactionId(3)).unwrap().dispute_state = DisputeState::Disputed;
+        assert_eq!(
+            store.insert(Transaction::deposit(ClientId(1), TransactionId(4), 1)),
+            Err(TransactionError::CapacityExceeded {
+                id: TransactionId(4)
+            })
+        );
+        assert_eq!(store.iter_disputed().count(), 2);
+    }
+
+    #[test]
+    fn inserting() {
+        let mut store = store(2);
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        assert_eq!(
+            store.insert(Transaction::withdrawal(ClientId(1), TransactionId(1), 1)),
+            Err(TransactionError::Conflict {
+                id: TransactionId(1)
+            })
+        );
+        assert_eq!(
+            store.insert(Transaction::deposit(ClientId(2), TransactionId(1), 1)),
+            Err(TransactionError::DuplicateClientId {
+                id: TransactionId(1),
+                original_client: ClientId(1),
+                new_client: ClientId(2)
+            })
+        );
+
+        // dropping the lock neither inserts nor evicts
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(2), 1))
+            .unwrap();
+        {
+            let _lock = store
+                .lock_for_insert(Transaction::deposit(ClientId(1), TransactionId(3), 1))
+                .unwrap();
+        }
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(TransactionId(1)));
+        assert!(!store.contains(TransactionId(3)));
+    }
+}
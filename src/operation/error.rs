@@ -1,17 +1,49 @@
 use thiserror::Error;
 
 use super::TransactionId;
+use crate::account::ClientId;
 
 pub type TransactionResult<T> = Result<T, TransactionError>;
 
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum TransactionError {
+    #[error("invalid transaction id '{id}'")]
+    InvalidTransactionId { id: u64 },
     #[error("transaction '{id}' not found")]
     NotFound { id: TransactionId },
     #[error("transaction '{id}' with different action already exists")]
     Conflict { id: TransactionId },
+    #[error(
+        "transaction '{id}' of client '{new_client}' already exists for client '{original_client}'"
+    )]
+    DuplicateClientId {
+        id: TransactionId,
+        original_client: ClientId,
+        new_client: ClientId,
+    },
+    #[error("transaction '{id}' rejected, all stored transactions are disputed")]
+    CapacityExceeded { id: TransactionId },
     #[error("failed to deserialize transaction '{id}' of type '{type_}': missing amount")]
     DeserializeMissingAmount { type_: String, id: TransactionId },
     #[error("failed to deserialize transaction '{id}': unknown type '{type_}'")]
     DeserializeUnknownType { type_: String, id: TransactionId },
+    #[error("malformed operation row '{row}'")]
+    MalformedRow { row: String },
+    #[error("transaction '{id}' has an invalid amount: {reason}")]
+    InvalidAmount {
+        id: TransactionId,
+        reason: AmountRejectionReason,
+    },
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountRejectionReason {
+    #[error("amount is negative")]
+    Negative,
+    #[error("amount is zero")]
+    Zero,
+    #[error("amount has '{scale}' decimal places, at most 4 are allowed")]
+    ExcessPrecision { scale: u32 },
+    #[error("amount exceeds the supported range")]
+    Overflow,
 }
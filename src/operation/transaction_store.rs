@@ -1,22 +1,181 @@
 use std::collections::{
     HashMap,
-    hash_map::{Entry, VacantEntry},
+    hash_map::{self, Entry, VacantEntry},
 };
 
-use crate::operation::{Transaction, TransactionError, TransactionId, TransactionResult};
+use derive_more::IntoIterator;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default)]
-pub struct TransactionStore(HashMap<TransactionId, TransactionStoreValue>);
+use crate::{
+    account::ClientId,
+    operation::{
+        HasClientId, HasTransactionId, Transaction, TransactionError, TransactionId,
+        TransactionResult,
+    },
+};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Default, Clone, IntoIterator)]
+pub struct TransactionStore {
+    #[into_iterator(owned, ref)]
+    transactions: HashMap<TransactionId, TransactionStoreValue>,
+    // ids of the transactions of each client in the order of their insertion
+    by_client: HashMap<ClientId, Vec<TransactionId>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct TransactionStoreValue {
     pub transaction: Transaction,
-    pub disputed: bool,
+    pub dispute_state: DisputeState,
+}
+
+impl TransactionStoreValue {
+    /// Value of a transaction which was never disputed.
+    pub fn new(transaction: Transaction) -> Self {
+        Self {
+            transaction,
+            dispute_state: DisputeState::Clean,
+        }
+    }
+}
+
+/// Mutable access to a stored transaction. Only the dispute state can be
+/// changed, the client of the transaction is part of the client index.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TransactionStoreValueMut<'a> {
+    pub transaction: &'a Transaction,
+    pub dispute_state: &'a mut DisputeState,
+}
+
+impl<'a> From<&'a mut TransactionStoreValue> for TransactionStoreValueMut<'a> {
+    fn from(value: &'a mut TransactionStoreValue) -> Self {
+        Self {
+            transaction: &value.transaction,
+            dispute_state: &mut value.dispute_state,
+        }
+    }
+}
+
+impl HasTransactionId for TransactionStoreValue {
+    fn transaction_id(&self) -> TransactionId {
+        self.transaction.tx
+    }
+}
+
+impl HasClientId for TransactionStoreValue {
+    fn client(&self) -> ClientId {
+        self.transaction.client
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeState {
+    /// The transaction was never disputed.
+    #[default]
+    Clean,
+    /// The transaction is currently disputed.
+    Disputed,
+    /// The dispute was resolved, the transaction can be disputed again.
+    Resolved,
+    /// The dispute ended with a chargeback, the transaction is closed for good.
+    Chargedback,
 }
 
 impl TransactionStore {
-    pub fn get_mut(&mut self, id: TransactionId) -> TransactionResult<&mut TransactionStoreValue> {
-        self.0.get_mut(&id).ok_or(TransactionError::NotFound { id })
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Approximate heap usage of the store in bytes, based on the capacity
+    /// of the underlying hash map and one control byte per entry.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        self.transactions.capacity() * (size_of::<(TransactionId, TransactionStoreValue)>() + 1)
+            + self.by_client.capacity() * (size_of::<(ClientId, Vec<TransactionId>)>() + 1)
+            + self.transactions.len() * size_of::<TransactionId>()
+    }
+
+    /// Removes all transactions but keeps the allocated memory.
+    pub fn clear(&mut self) {
+        self.transactions.clear();
+        self.by_client.clear();
+    }
+
+    pub fn contains(&self, id: TransactionId) -> bool {
+        self.transactions.contains_key(&id)
+    }
+
+    /// Returns `None` if the transaction is unknown.
+    pub fn is_disputed(&self, id: TransactionId) -> Option<bool> {
+        self.transactions
+            .get(&id)
+            .map(|value| value.dispute_state == DisputeState::Disputed)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&TransactionId, &TransactionStoreValue)> {
+        self.transactions.iter()
+    }
+
+    pub fn iter_disputed(&self) -> impl Iterator<Item = (&TransactionId, &TransactionStoreValue)> {
+        self.iter()
+            .filter(|(_, value)| value.dispute_state == DisputeState::Disputed)
+    }
+
+    pub fn get_mut(
+        &mut self,
+        id: TransactionId,
+    ) -> TransactionResult<TransactionStoreValueMut<'_>> {
+        self.transactions
+            .get_mut(&id)
+            .map(TransactionStoreValueMut::from)
+            .ok_or(TransactionError::NotFound { id })
+    }
+
+    /// Ids of all transactions of the client in the order they were inserted.
+    pub fn transaction_ids_for_client(&self, client: ClientId) -> &[TransactionId] {
+        self.by_client.get(&client).map_or(&[], Vec::as_slice)
+    }
+
+    /// All transactions of the client in the order they were inserted. Only
+    /// looks at the transactions of the client instead of the whole store.
+    pub fn all_transactions_for_client(&self, client: ClientId) -> Vec<&TransactionStoreValue> {
+        self.transaction_ids_for_client(client)
+            .iter()
+            .map(|id| &self.transactions[id])
+            .collect()
+    }
+
+    /// Removes all transactions of the client which are neither disputed nor
+    /// charged back, i.e. clean or resolved ones, and returns their number.
+    /// Evicted transactions can no longer be disputed and their ids are no
+    /// longer detected as duplicates.
+    pub fn evict_resolved(&mut self, client: ClientId) -> usize {
+        let Some(ids) = self.by_client.get_mut(&client) else {
+            return 0;
+        };
+
+        // only the transactions of the client are looked at
+        let len = ids.len();
+        ids.retain(|id| {
+            let evict = matches!(
+                self.transactions[id].dispute_state,
+                DisputeState::Clean | DisputeState::Resolved
+            );
+            if evict {
+                self.transactions.remove(id);
+            }
+            !evict
+        });
+
+        let evicted = len - ids.len();
+        if ids.is_empty() {
+            self.by_client.remove(&client);
+        }
+        evicted
     }
 
     // currently only used within tests
@@ -26,29 +185,64 @@ impl TransactionStore {
         Ok(())
     }
 
+    /// Fails with [`TransactionError::DuplicateClientId`] if the id is already
+    /// taken by a transaction of another client, otherwise with
+    /// [`TransactionError::Conflict`] if the id is already taken.
     pub fn lock_for_insert(&mut self, tx: Transaction) -> TransactionResult<LockForInsert<'_>> {
-        match self.0.entry(tx.tx) {
-            Entry::Occupied(_) => Err(TransactionError::Conflict { id: tx.tx }),
+        match self.transactions.entry(tx.tx) {
+            Entry::Occupied(occupied) => {
+                let original_client = occupied.get().transaction.client;
+                if original_client != tx.client {
+                    Err(TransactionError::DuplicateClientId {
+                        id: tx.tx,
+                        original_client,
+                        new_client: tx.client,
+                    })
+                } else {
+                    Err(TransactionError::Conflict { id: tx.tx })
+                }
+            }
             Entry::Vacant(vacant) => Ok(LockForInsert(
                 vacant,
-                TransactionStoreValue {
-                    transaction: tx,
-                    disputed: false,
-                },
+                &mut self.by_client,
+                TransactionStoreValue::new(tx),
             )),
         }
     }
 }
 
+type IterMut<'a> = hash_map::IterMut<'a, TransactionId, TransactionStoreValue>;
+
+impl<'a> IntoIterator for &'a mut TransactionStore {
+    type Item = (&'a TransactionId, TransactionStoreValueMut<'a>);
+    type IntoIter = std::iter::Map<
+        IterMut<'a>,
+        fn((&'a TransactionId, &'a mut TransactionStoreValue)) -> Self::Item,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.transactions
+            .iter_mut()
+            .map(|(id, value)| (id, value.into()))
+    }
+}
+
 #[derive(Debug)]
 pub struct LockForInsert<'a>(
     VacantEntry<'a, TransactionId, TransactionStoreValue>,
+    &'a mut HashMap<ClientId, Vec<TransactionId>>,
     TransactionStoreValue,
 );
 
 impl LockForInsert<'_> {
+    /// Inserts the transaction and adds it to the index of its client. Until
+    /// then neither the store nor the index are changed.
     pub fn finish(self) {
-        let LockForInsert(entry, tx) = self;
+        let LockForInsert(entry, by_client, tx) = self;
+        by_client
+            .entry(tx.transaction.client)
+            .or_default()
+            .push(tx.transaction.tx);
         entry.insert(tx);
     }
 }
@@ -61,43 +255,208 @@ mod test {
     fn get_mut_unknown_id() {
         let mut store = TransactionStore::default();
 
-        assert_eq!(store.get_mut(1), Err(TransactionError::NotFound { id: 1 }));
+        assert_eq!(
+            store.get_mut(TransactionId(1)),
+            Err(TransactionError::NotFound {
+                id: TransactionId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn queries() {
+        let mut store = TransactionStore::default();
+        assert!(store.is_empty());
+        assert!(!store.contains(TransactionId(1)));
+        assert_eq!(store.is_disputed(TransactionId(1)), None);
+
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(2), 1))
+            .unwrap();
+        *store.get_mut(TransactionId(2)).unwrap().dispute_state = DisputeState::Disputed;
+
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(TransactionId(1)));
+        assert_eq!(store.is_disputed(TransactionId(1)), Some(false));
+        assert_eq!(store.is_disputed(TransactionId(2)), Some(true));
+    }
+
+    #[test]
+    fn iterating() {
+        let mut store = TransactionStore::default();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(2), TransactionId(2), 2))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(3), TransactionId(3), 3))
+            .unwrap();
+        *store.get_mut(TransactionId(2)).unwrap().dispute_state = DisputeState::Disputed;
+
+        let mut ids = store.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            vec![TransactionId(1), TransactionId(2), TransactionId(3)]
+        );
+
+        assert_eq!(
+            store.iter_disputed().collect::<Vec<_>>(),
+            vec![(
+                &TransactionId(2),
+                &TransactionStoreValue {
+                    transaction: Transaction::deposit(ClientId(2), TransactionId(2), 2),
+                    dispute_state: DisputeState::Disputed,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn evict_resolved() {
+        let mut store = TransactionStore::default();
+        for id in 1..=4 {
+            store
+                .insert(Transaction::deposit(ClientId(1), TransactionId(id), 1))
+                .unwrap();
+        }
+        store
+            .insert(Transaction::deposit(ClientId(2), TransactionId(5), 1))
+            .unwrap();
+        *store.get_mut(TransactionId(2)).unwrap().dispute_state = DisputeState::Disputed;
+        *store.get_mut(TransactionId(3)).unwrap().dispute_state = DisputeState::Resolved;
+        *store.get_mut(TransactionId(4)).unwrap().dispute_state = DisputeState::Chargedback;
+
+        assert_eq!(store.evict_resolved(ClientId(1)), 2);
+        assert!(!store.contains(TransactionId(1)));
+        assert!(store.contains(TransactionId(2)));
+        assert!(!store.contains(TransactionId(3)));
+        assert!(store.contains(TransactionId(4)));
+        assert!(store.contains(TransactionId(5)));
+
+        assert_eq!(store.evict_resolved(ClientId(1)), 0);
+    }
+
+    #[test]
+    fn all_transactions_for_client() {
+        let mut store = TransactionStore::default();
+        for id in [3, 1, 2] {
+            store
+                .insert(Transaction::deposit(ClientId(1), TransactionId(id), 1))
+                .unwrap();
+        }
+        store
+            .insert(Transaction::deposit(ClientId(2), TransactionId(4), 1))
+            .unwrap();
+        *store.get_mut(TransactionId(1)).unwrap().dispute_state = DisputeState::Disputed;
+
+        let ids = |store: &TransactionStore, client| {
+            store
+                .all_transactions_for_client(client)
+                .into_iter()
+                .map(|value| value.transaction.tx.get())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&store, ClientId(1)), vec![3, 1, 2]);
+        assert_eq!(ids(&store, ClientId(2)), vec![4]);
+        assert_eq!(ids(&store, ClientId(3)), Vec::<u64>::new());
+
+        store.evict_resolved(ClientId(1));
+        assert_eq!(ids(&store, ClientId(1)), vec![1]);
+
+        store.clear();
+        assert_eq!(ids(&store, ClientId(2)), Vec::<u64>::new());
     }
 
     #[test]
     fn inserting() {
         let mut store = TransactionStore::default();
 
-        store.insert(Transaction::deposit(1, 1, 1)).unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
         assert_eq!(
-            store.insert(Transaction::withdrawal(1, 1, 1)),
-            Err(TransactionError::Conflict { id: 1 })
+            store.insert(Transaction::withdrawal(ClientId(1), TransactionId(1), 1)),
+            Err(TransactionError::Conflict {
+                id: TransactionId(1)
+            })
         );
         assert_eq!(
             store
-                .lock_for_insert(Transaction::withdrawal(1, 1, 1))
+                .lock_for_insert(Transaction::withdrawal(ClientId(1), TransactionId(1), 1))
                 .unwrap_err(),
-            TransactionError::Conflict { id: 1 }
+            TransactionError::Conflict {
+                id: TransactionId(1)
+            }
         );
 
         // dont make use of lock so nothing is inserted
         // when it is dropped at the end of the scope
         {
             let _lock = store
-                .lock_for_insert(Transaction::deposit(2, 2, 2))
+                .lock_for_insert(Transaction::deposit(ClientId(2), TransactionId(2), 2))
                 .unwrap();
         }
+        assert!(store.transaction_ids_for_client(ClientId(2)).is_empty());
 
         // second attempt can't error but third will
         store
-            .lock_for_insert(Transaction::deposit(2, 2, 2))
+            .lock_for_insert(Transaction::deposit(ClientId(2), TransactionId(2), 2))
             .unwrap()
             .finish();
         assert_eq!(
             store
-                .lock_for_insert(Transaction::deposit(2, 2, 2))
+                .lock_for_insert(Transaction::deposit(ClientId(2), TransactionId(2), 2))
                 .unwrap_err(),
-            TransactionError::Conflict { id: 2 }
+            TransactionError::Conflict {
+                id: TransactionId(2)
+            }
+        );
+
+        // same id from another client
+        assert_eq!(
+            store.insert(Transaction::deposit(ClientId(3), TransactionId(2), 2)),
+            Err(TransactionError::DuplicateClientId {
+                id: TransactionId(2),
+                original_client: ClientId(2),
+                new_client: ClientId(3)
+            })
+        );
+        assert_eq!(
+            store.transaction_ids_for_client(ClientId(2)),
+            &[TransactionId(2)]
+        );
+        assert!(store.transaction_ids_for_client(ClientId(3)).is_empty());
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut store = TransactionStore::default();
+        store
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        store
+            .insert(Transaction::deposit(ClientId(2), TransactionId(2), 20))
+            .unwrap();
+
+        for (_, value) in &mut store {
+            *value.dispute_state = DisputeState::Disputed;
+        }
+        assert_eq!(
+            (&store)
+                .into_iter()
+                .filter(|(_, value)| value.dispute_state == DisputeState::Disputed)
+                .count(),
+            2
         );
+
+        let mut ids = store.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![TransactionId(1), TransactionId(2)]);
     }
 }
@@ -1,64 +1,679 @@
+#[cfg(feature = "audit_log")]
+pub use audit::*;
+pub use checkpoint::*;
+pub use config::*;
 pub use error::*;
+pub use processor::*;
+pub use report::*;
 
+#[cfg(feature = "audit_log")]
+mod audit;
+mod checkpoint;
+mod config;
 mod error;
 mod processor;
+mod report;
 
 use std::{
-    hash::{DefaultHasher, Hash, Hasher},
+    fs::File,
+    hash::BuildHasher,
+    io::{BufRead, BufReader, Cursor, Read},
+    num::NonZeroUsize,
     path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
     thread::JoinHandle,
 };
 
-use crossbeam::channel::{self, Sender};
+use crossbeam::channel::{self, Receiver, Sender};
 
-use crate::{account::AccountStore, csv_reader_builder, operation::Operation};
+use crate::{
+    account::{AccountStore, ClientId},
+    csv_reader_builder_with_config,
+    operation::Operation,
+};
 
-use self::processor::PaymentProcessor;
+type ProcessorHandle = JoinHandle<PaymentResult<(AccountStore, ProcessingReport)>>;
 
 pub struct PaymentEngine {
-    sender: Box<[Sender<Operation>]>,
-    processor_handle: Box<[JoinHandle<PaymentResult<AccountStore>>]>,
+    sender: Box<[Sender<ProcessorMessage>]>,
+    processor_handle: Box<[ProcessorHandle]>,
+    max_reported_failures: usize,
+    progress_interval: u64,
+    dispatch_hasher: DispatchHasher,
+    error_receiver: Option<Receiver<(Operation, PaymentError)>>,
+    // memory estimate published by each processor
+    memory_estimates: Box<[Arc<AtomicUsize>]>,
+    // number of operations submitted via `dispatch`
+    dispatched: usize,
 }
 
 impl PaymentEngine {
-    pub fn new(worker: usize) -> Self {
-        let (sender, processor_handle): (Vec<_>, Vec<_>) = (0..worker)
-            .map(|_| {
-                let (sender, receiver) = channel::unbounded();
-                let processor = PaymentProcessor::new();
+    pub fn new(worker: NonZeroUsize) -> Self {
+        Self::new_with_config(EngineConfig {
+            workers: worker,
+            ..Default::default()
+        })
+    }
+
+    /// Creates an engine with exactly one worker. All operations are then
+    /// processed in the exact order they were submitted, independent of
+    /// their client, and all transaction ids share a single transaction
+    /// store. This makes results fully deterministic, e.g. for testing.
+    pub fn new_single_threaded() -> Self {
+        Self::new(NonZeroUsize::MIN)
+    }
+
+    /// Creates an engine which dispatches the operations by the hash of their
+    /// client id seeded with `seed`, see `DispatchHasher::seeded`.
+    pub fn new_deterministic(worker: NonZeroUsize, seed: u64) -> Self {
+        Self::new_with_config(EngineConfig {
+            workers: worker,
+            dispatch_hasher: DispatchHasher::seeded(seed),
+            ..Default::default()
+        })
+    }
+
+    /// Creates an engine which dispatches the operations by the hash of their
+    /// client id built with `build_hasher`, see `DispatchHasher::new`.
+    pub fn new_with_hash_dispatch<S>(worker: NonZeroUsize, build_hasher: S) -> Self
+    where
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        Self::new_with_config(EngineConfig {
+            workers: worker,
+            dispatch_hasher: DispatchHasher::new(build_hasher),
+            ..Default::default()
+        })
+    }
+
+    /// Creates an engine which dispatches the operations to the worker
+    /// returned by `assign`, see `DispatchHasher::custom`.
+    pub fn new_with_custom_dispatch<F>(worker: NonZeroUsize, assign: F) -> Self
+    where
+        F: Fn(ClientId, usize) -> usize + Send + Sync + 'static,
+    {
+        Self::new_with_config(EngineConfig {
+            workers: worker,
+            dispatch_hasher: DispatchHasher::custom(assign),
+            ..Default::default()
+        })
+    }
+
+    pub fn new_with_config(config: EngineConfig) -> Self {
+        Self::new_with_initial_state(config, AccountStore::default())
+    }
+
+    /// Creates an engine whose processors start with the given accounts
+    /// instead of empty ones. Each account is handed to the processor its
+    /// client is dispatched to. Only accounts are restored, conflicts that
+    /// reference transactions of previous runs are therefore rejected.
+    pub fn new_with_initial_state(config: EngineConfig, initial: AccountStore) -> Self {
+        Self::spawn(config, initial, None)
+    }
+
+    /// Creates an engine which keeps the errors of all failed operations until
+    /// they are retrieved with `drain_errors`, independent of the error mode.
+    pub fn new_with_error_collection(config: EngineConfig) -> Self {
+        let (error_sink, error_receiver) = channel::unbounded();
+        let mut engine = Self::spawn(config, AccountStore::default(), Some(error_sink));
+        engine.error_receiver = Some(error_receiver);
+        engine
+    }
+
+    // Spawns the processors, if an error sink is given every
+    // failed operation is sent to it.
+    fn spawn(
+        config: EngineConfig,
+        initial: AccountStore,
+        error_sink: Option<Sender<(Operation, PaymentError)>>,
+    ) -> Self {
+        let mut shards = (0..config.workers.get())
+            .map(|_| AccountStore::default())
+            .collect::<Vec<_>>();
+        for (client, account) in initial.into_iter() {
+            let index = config.dispatch_hasher.worker_index(client, config.workers);
+            shards[index].insert(account);
+        }
+
+        let ((sender, processor_handle), memory_estimates): ((Vec<_>, Vec<_>), Vec<_>) = shards
+            .into_iter()
+            .enumerate()
+            .map(|(worker, accounts)| {
+                let (sender, receiver) = match config.channel_capacity {
+                    Some(capacity) => channel::bounded(capacity),
+                    None => channel::unbounded(),
+                };
+                let memory_estimate = Arc::new(AtomicUsize::new(0));
+                let mut processor = PaymentProcessor::with_state(&config, accounts)
+                    .with_worker(worker)
+                    .with_memory_estimate(memory_estimate.clone());
+                if let Some(error_sink) = &error_sink {
+                    processor = processor.with_error_sink(error_sink.clone());
+                }
 
                 let handle = std::thread::spawn(move || processor.run(receiver));
-                (sender, handle)
+                ((sender, handle), memory_estimate)
             })
             .unzip();
 
         Self {
             sender: sender.into_boxed_slice(),
             processor_handle: processor_handle.into_boxed_slice(),
+            max_reported_failures: config.max_reported_failures,
+            progress_interval: config.progress_interval,
+            dispatch_hasher: config.dispatch_hasher,
+            error_receiver: None,
+            memory_estimates: memory_estimates.into_boxed_slice(),
+            dispatched: 0,
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.processor_handle.len()
+    }
+
+    /// Whether all processors are still running. A processor only stops
+    /// early if it aborted, e.g. on the first failure with `ErrorMode::Fail`.
+    pub fn is_running(&self) -> bool {
+        self.processor_handle
+            .iter()
+            .all(|handle| !handle.is_finished())
+    }
+
+    /// Approximate heap usage of the account and transaction stores of all
+    /// processors in bytes, see `PaymentProcessor::estimate_memory_bytes`.
+    /// The processors publish their estimate after every operation, so
+    /// operations which are still queued aren't included.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        self.memory_estimates
+            .iter()
+            .map(|estimate| estimate.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn process_csv<P: AsRef<Path>>(path: P) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_csv_with_config(path, EngineConfig::default())
+    }
+
+    pub fn process_csv_with_config<P: AsRef<Path>>(
+        path: P,
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        let file = File::open(path)?;
+        Self::process_reader_with_config(file, config)
+    }
+
+    /// Same as `process_csv`, but calls `on_row` for every deserialized
+    /// operation before it's dispatched, e.g. to log it. Operations for which
+    /// `on_row` returns `false` are skipped and not counted in the report.
+    pub fn process_csv_with_callback<P, F>(
+        path: P,
+        on_row: F,
+    ) -> EngineResult<(AccountStore, ProcessingReport)>
+    where
+        P: AsRef<Path>,
+        F: Fn(&Operation) -> bool,
+    {
+        let config = EngineConfig::default();
+        let file = File::open(path)?;
+        let rows = csv_reader_builder_with_config(&config.csv)
+            .from_reader(file)
+            .into_deserialize::<Operation>();
+
+        Self::process_rows_filtered(rows, config, AccountStore::default(), on_row, None)
+    }
+
+    /// Processes the CSV file in chunks of `chunk_size` rows. The processors
+    /// are notified at the end of each chunk and handle their transactions
+    /// according to `mode`, the accounts are always kept.
+    pub fn process_csv_chunked<P: AsRef<Path>>(
+        path: P,
+        chunk_size: NonZeroUsize,
+        mode: ChunkMode,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        let config = EngineConfig::default();
+        let file = File::open(path)?;
+        let rows = csv_reader_builder_with_config(&config.csv)
+            .from_reader(file)
+            .into_deserialize::<Operation>();
+
+        Self::process_rows_filtered(
+            rows,
+            config,
+            AccountStore::default(),
+            |_| true,
+            Some((chunk_size, mode)),
+        )
+    }
+
+    /// Processes multiple CSV files as one concatenated stream of operations
+    /// in the given order. Transaction ids are shared across all files, so
+    /// conflicts can reference transactions of previous files and duplicate
+    /// ids are rejected the same way as within a single file. All files are
+    /// opened before processing starts.
+    pub fn process_multiple_csv<P: AsRef<Path>>(
+        paths: &[P],
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_multiple_csv_with_config(paths, EngineConfig::default())
+    }
+
+    pub fn process_multiple_csv_with_config<P: AsRef<Path>>(
+        paths: &[P],
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        let files = paths
+            .iter()
+            .map(File::open)
+            .collect::<Result<Vec<_>, _>>()?;
+        let csv = config.csv;
+        let rows = files.into_iter().flat_map(move |file| {
+            csv_reader_builder_with_config(&csv)
+                .from_reader(file)
+                .into_deserialize::<Operation>()
+        });
+
+        Self::process_rows(rows, config)
+    }
+
+    /// Processes multiple CSV files concurrently with one reading thread per
+    /// file. Transaction ids are shared across all files like with
+    /// `process_multiple_csv`, but the relative order of operations from
+    /// different files is undefined. Operations of the same client should
+    /// therefore be contained in a single file.
+    pub fn process_csv_parallel<P: AsRef<Path>>(
+        paths: &[P],
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_csv_parallel_with_config(paths, EngineConfig::default())
+    }
+
+    pub fn process_csv_parallel_with_config<P: AsRef<Path>>(
+        paths: &[P],
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        let files = paths
+            .iter()
+            .map(File::open)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (sender, receiver) = match config.channel_capacity {
+            Some(capacity) => channel::bounded(capacity),
+            None => channel::unbounded(),
+        };
+        let readers = files
+            .into_iter()
+            .map(|file| {
+                let sender = sender.clone();
+                let csv = config.csv;
+                std::thread::spawn(move || {
+                    let rows = csv_reader_builder_with_config(&csv)
+                        .from_reader(file)
+                        .into_deserialize::<Operation>();
+                    for row in rows {
+                        // the receiver is only dropped if processing was aborted
+                        if sender.send(row).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        // the receiver finishes as soon as all readers dropped their sender
+        drop(sender);
+
+        let result = Self::process_rows(receiver, config);
+        for reader in readers {
+            reader.join().map_err(|_| PaymentError::JoiningProcessors)?;
+        }
+
+        result
+    }
+
+    pub fn process_reader<R: Read>(reader: R) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_reader_with_config(reader, EngineConfig::default())
+    }
+
+    /// Processes in-memory CSV data. The bytes are expected to be valid UTF-8
+    /// encoded CSV in the same format as the files given to `process_csv`.
+    pub fn process_bytes(data: &[u8]) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_reader(Cursor::new(data))
+    }
+
+    /// Processes CSV data given as string, see `process_bytes`.
+    pub fn process_string(data: &str) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_bytes(data.as_bytes())
+    }
+
+    pub fn process_reader_with_config<R: Read>(
+        reader: R,
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        let rows = csv_reader_builder_with_config(&config.csv)
+            .from_reader(reader)
+            .into_deserialize::<Operation>();
+
+        Self::process_rows(rows, config)
+    }
+
+    /// Processes newline-delimited JSON (NDJSON), each line is expected to be
+    /// a JSON object with the same fields as the CSV columns, see
+    /// `Operation::from_json_str`. Empty lines are ignored.
+    pub fn process_ndjson<R: Read>(reader: R) -> EngineResult<(AccountStore, ProcessingReport)> {
+        Self::process_ndjson_with_config(reader, EngineConfig::default())
+    }
+
+    pub fn process_ndjson_with_config<R: Read>(
+        reader: R,
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)> {
+        // We deserialize line by line instead of using a streaming
+        // deserializer over the whole input, as the latter can't
+        // recover from a malformed line to skip it.
+        //
+        // Reading errors can't be skipped like malformed lines
+        // so we stop reading and report them after processing.
+        let mut read_error = None;
+        let rows = BufReader::new(reader)
+            .lines()
+            .map_while(|line| line.map_err(|err| read_error = Some(err)).ok())
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Operation::from_json_str(&line));
+
+        let result = Self::process_rows(rows, config);
+        if let Some(err) = read_error {
+            return Err(err.into());
         }
+
+        result
     }
 
-    pub fn process_csv<P: AsRef<Path>>(path: P) -> anyhow::Result<AccountStore> {
-        let operations = csv_reader_builder()
-            .from_path(path)?
-            .into_deserialize::<Operation>()
-            .filter_map(|res| {
+    // Processes deserialized input rows. Rows that couldn't be deserialized
+    // are handled according to the configured error mode.
+    fn process_rows<I, E>(
+        rows: I,
+        config: EngineConfig,
+    ) -> EngineResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Result<Operation, E>>,
+        E: Into<EngineError>,
+    {
+        Self::process_rows_filtered(rows, config, AccountStore::default(), |_| true, None)
+    }
+
+    // Same as `process_rows`, but starts with the `initial` accounts and only
+    // dispatches operations accepted by `filter`. The indices of the
+    // remaining rows are kept for the report. With `chunks` the end of each
+    // chunk of the given number of rows is sent to all processors.
+    fn process_rows_filtered<I, E, F>(
+        rows: I,
+        config: EngineConfig,
+        initial: AccountStore,
+        filter: F,
+        chunks: Option<(NonZeroUsize, ChunkMode)>,
+    ) -> EngineResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Result<Operation, E>>,
+        E: Into<EngineError>,
+        F: Fn(&Operation) -> bool,
+    {
+        let error_mode = config.effective_error_mode();
+        let max_reported_failures = config.max_reported_failures;
+
+        let mut row_error = None;
+        let mut rows_report = ProcessingReport::default();
+        let messages = rows
+            .into_iter()
+            .enumerate()
+            .map_while(|(index, res)| match res {
+                Ok(operation) => Some((index, filter(&operation).then_some(operation))),
+                Err(err) if error_mode == ErrorMode::Fail => {
+                    row_error = Some(err.into());
+                    None
+                }
                 // we skip lines that can't be deserialized and consider them as wrong
-                res.ok()
+                Err(err) => {
+                    rows_report.skipped_rows += 1;
+                    if error_mode == ErrorMode::Collect
+                        && rows_report.failures.len() < max_reported_failures
+                    {
+                        rows_report.failures.push((index, err.into()));
+                    }
+                    Some((index, None))
+                }
+            })
+            .flat_map(|(index, operation)| {
+                // skipped rows are part of the chunk as well
+                let end_of_chunk = chunks
+                    .filter(|(size, _)| index > 0 && index % size.get() == 0)
+                    .map(|(_, mode)| ProcessorMessage::EndOfChunk(mode));
+                end_of_chunk
+                    .into_iter()
+                    .chain(operation.map(|operation| ProcessorMessage::Operation(index, operation)))
             });
 
-        let worker = std::thread::available_parallelism()?.get();
-        let accounts = PaymentEngine::new(worker).process(operations)?;
+        let (accounts, mut report) =
+            Self::new_with_initial_state(config, initial).process_messages(messages, |_, _| {})?;
+        if let Some(err) = row_error {
+            return Err(err);
+        }
 
-        Ok(accounts)
+        report.merge(rows_report, max_reported_failures);
+        Ok((accounts, report))
+    }
+
+    /// Processes the operations with the default configuration but dispatches
+    /// them to the worker returned by `assign`, see `DispatchHasher::custom`.
+    pub fn process_with_worker_assignment<I, F>(
+        operations: I,
+        assign: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+        F: Fn(ClientId, usize) -> usize + Send + Sync + 'static,
+    {
+        Self::process_iter(
+            operations,
+            EngineConfig {
+                dispatch_hasher: DispatchHasher::custom(assign),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Processes the operations with an engine created from the given
+    /// configuration. This is the core of all other `process_*` functions,
+    /// which only turn their input into operations.
+    pub fn process_iter<I>(
+        operations: I,
+        config: EngineConfig,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        Self::process_iter_indexed(operations.into_iter().enumerate(), config)
+    }
+
+    // Same as `process_iter` but with the index of each operation in the
+    // input, see `process_indexed`.
+    fn process_iter_indexed<I>(
+        operations: I,
+        config: EngineConfig,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = (usize, Operation)>,
+    {
+        Self::new_with_config(config).process_indexed(operations, |_, _| {})
+    }
+
+    /// Processes the operations and calls `on_error` for every failed
+    /// operation with the operation and its error, see
+    /// `process_with_error_sink_and_config`.
+    pub fn process_with_error_sink<I, F>(
+        operations: I,
+        on_error: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+        F: FnMut(Operation, PaymentError),
+    {
+        Self::process_with_error_sink_and_config(operations, EngineConfig::default(), on_error)
+    }
+
+    /// Processes the operations and calls `on_error` for every failed
+    /// operation with the operation and its error. The callback is called on
+    /// the calling thread while the operations are dispatched, failures of
+    /// different clients may therefore be reported out of input order.
+    /// The error mode applies independently of the callback.
+    pub fn process_with_error_sink_and_config<I, F>(
+        operations: I,
+        config: EngineConfig,
+        on_error: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+        F: FnMut(Operation, PaymentError),
+    {
+        Self::new_with_error_collection(config)
+            .process_indexed(operations.into_iter().enumerate(), on_error)
+    }
+
+    /// Processes the operations on top of the given accounts, e.g. the result
+    /// of a previous run, see `new_with_initial_state`.
+    pub fn process_with_initial_state<I>(
+        initial: AccountStore,
+        operations: I,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        Self::new_with_initial_state(EngineConfig::default(), initial).process(operations)
+    }
+
+    pub fn process<I>(self, operations: I) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = Operation>,
+    {
+        self.process_indexed(operations.into_iter().enumerate(), |_, _| {})
     }
 
-    pub fn process<I>(mut self, operations: I) -> PaymentResult<AccountStore>
+    /// Same as `process`, but calls `on_progress` on the calling thread with
+    /// the number of operations dispatched so far every
+    /// `EngineConfig::progress_interval` operations.
+    pub fn process_with_progress<I, F>(
+        self,
+        operations: I,
+        on_progress: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
     where
         I: IntoIterator<Item = Operation>,
+        F: Fn(u64),
     {
-        for operation in operations.into_iter() {
-            dispatch_operation(operation, &self.sender)?;
+        let interval = self.progress_interval.max(1);
+        let mut dispatched = 0;
+        let operations = operations.into_iter().inspect(|_| {
+            dispatched += 1;
+            if dispatched % interval == 0 {
+                on_progress(dispatched);
+            }
+        });
+
+        self.process(operations)
+    }
+
+    /// Dispatches a single operation to its processor, e.g. for operations
+    /// of an external source that is drained by the caller. The results are
+    /// returned by `shutdown`.
+    pub fn dispatch(&mut self, operation: Operation) -> PaymentResult<()> {
+        dispatch_operation(
+            self.dispatched,
+            operation,
+            &self.sender,
+            &self.dispatch_hasher,
+        )?;
+        self.dispatched += 1;
+        Ok(())
+    }
+
+    /// Returns the errors of all operations that failed since the last call,
+    /// e.g. to check for failures while dispatching a stream of operations.
+    /// Always empty unless the engine was created with
+    /// `new_with_error_collection`. Operations which are still queued may
+    /// fail later, so errors can show up after the operation was dispatched.
+    pub fn drain_errors(&mut self) -> Vec<PaymentError> {
+        self.error_receiver
+            .as_ref()
+            .map(|receiver| receiver.try_iter().map(|(_, err)| err).collect())
+            .unwrap_or_default()
+    }
+
+    /// Stops accepting operations, waits until the processors processed all
+    /// dispatched operations and returns the resulting accounts.
+    pub fn shutdown(self) -> PaymentResult<AccountStore> {
+        let (accounts, _) = self.process_indexed(std::iter::empty(), |_, _| {})?;
+        Ok(accounts)
+    }
+
+    // Processes the given operations together with their index in the input,
+    // the index is only used to identify failures in the processing report.
+    // Failures received from the error sink are passed to `on_error`.
+    fn process_indexed<I, F>(
+        self,
+        operations: I,
+        on_error: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = (usize, Operation)>,
+        F: FnMut(Operation, PaymentError),
+    {
+        let messages = operations
+            .into_iter()
+            .map(|(index, operation)| ProcessorMessage::Operation(index, operation));
+        self.process_messages(messages, on_error)
+    }
+
+    // Same as `process_indexed`, but operations are sent
+    // as part of all messages the processors receive.
+    fn process_messages<I, F>(
+        mut self,
+        messages: I,
+        mut on_error: F,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)>
+    where
+        I: IntoIterator<Item = ProcessorMessage>,
+        F: FnMut(Operation, PaymentError),
+    {
+        let error_receiver = self.error_receiver.take();
+        let mut drain_errors = || {
+            if let Some(receiver) = &error_receiver {
+                receiver
+                    .try_iter()
+                    .for_each(|(operation, err)| on_error(operation, err));
+            }
+        };
+
+        let mut dispatch_error = None;
+        for message in messages.into_iter() {
+            let ProcessorMessage::Operation(index, operation) = message else {
+                // all processors are notified, a processor that can't be
+                // notified aborted and reports the reason when joining it
+                for sender in self.sender.iter() {
+                    let _ = sender.send(message);
+                }
+                continue;
+            };
+
+            if let Err(err) =
+                dispatch_operation(index, operation, &self.sender, &self.dispatch_hasher)
+            {
+                // A processor only stops receiving if it aborted, the
+                // actual reason is reported when joining it below.
+                dispatch_error = Some(err);
+                break;
+            }
+
+            drain_errors();
         }
 
         // dropping all the sender so the receivers will
@@ -66,41 +681,59 @@ impl PaymentEngine {
         drop(std::mem::take(&mut self.sender));
 
         let mut accounts = AccountStore::default();
+        let mut report = ProcessingReport::default();
         for handle in std::mem::take(&mut self.processor_handle).into_iter() {
-            let store = handle
+            let (store, processor_report) = handle
                 .join()
                 .map_err(|_| PaymentError::JoiningProcessors)
                 .flatten()?;
 
-            accounts.extend(store);
+            // each client is dispatched to exactly one processor, so a
+            // duplicate account indicates a broken dispatch
+            accounts.extend(store)?;
+            report.merge(processor_report, self.max_reported_failures);
         }
 
-        Ok(accounts)
+        // all processors finished, so this drains the remaining errors
+        drain_errors();
+
+        if let Some(err) = dispatch_error {
+            return Err(err);
+        }
+
+        Ok((accounts, report))
     }
 }
 
 // Operations with the same client id get dispatched to the same processor
 // and therefore to the same sender. To achieve this we hash the client id
-// and send it to the sender with the same index as the hash modulo the
-// number of senders. This is important to avoid races between different
-// operations. For example the transaction order of a deposit and withdrawal
-// must never change otherwise it could be that we ignore a withdrawal if it
-// comes before a deposit that gives us enough credit to cover it. The same
-// goes for conflict operations like dispute. It must be ensured that a
-// dispute reaches the processor of its client so that the disputed transaction
-// is actually present on the processor.
-fn dispatch_operation(operation: Operation, sender: &[Sender<Operation>]) -> PaymentResult<()> {
+// with the configured, deterministic dispatch hasher and send it to the
+// sender with the same index as the hash modulo the number of senders. This
+// is important to avoid races between different operations. For example the
+// transaction order of a deposit and withdrawal must never change otherwise
+// it could be that we ignore a withdrawal if it comes before a deposit that
+// gives us enough credit to cover it. The same goes for conflict operations
+// like dispute. It must be ensured that a dispute reaches the processor of
+// its client so that the disputed transaction is actually present on the
+// processor.
+fn dispatch_operation(
+    index: usize,
+    operation: Operation,
+    sender: &[Sender<ProcessorMessage>],
+    hasher: &DispatchHasher,
+) -> PaymentResult<()> {
     let client = operation.client();
 
-    let mut hasher = DefaultHasher::new();
-    client.hash(&mut hasher);
-    let hash = hasher.finish();
+    let sender = NonZeroUsize::new(sender.len())
+        .and_then(|workers| sender.get(hasher.worker_index(client, workers)))
+        .expect(
+            "sender should exist as we created the index by modulo the length of the sender array",
+        );
 
-    let sender = sender.get((hash % (sender.len() as u64)) as usize).expect(
-        "sender should exist as we created the index by modulo the length of the sender array",
-    );
-
-    if sender.send(operation).is_err() {
+    if sender
+        .send(ProcessorMessage::Operation(index, operation))
+        .is_err()
+    {
         return Err(PaymentError::DispatchOperation { client });
     }
 
@@ -113,15 +746,695 @@ mod test {
 
     use crossbeam::channel::{self, Receiver};
     use itertools::Itertools;
+    use proptest::prelude::*;
     use rand::seq::SliceRandom;
+    use rust_decimal::Decimal;
 
     use crate::{
-        account::ClientId,
-        operation::{Conflict, Transaction},
+        CsvConfig,
+        account::{Account, AccountError, ClientId},
+        csv_reader_builder,
+        operation::{Conflict, Transaction, TransactionError, TransactionId},
     };
 
     use super::*;
 
+    proptest! {
+        #[test]
+        fn arbitrary_operations(operations in prop::collection::vec(any::<Operation>(), 0..200)) {
+            let (accounts, report) = PaymentEngine::new(NonZeroUsize::new(4).unwrap()).process(operations.clone()).unwrap();
+
+            prop_assert_eq!(report.operations_received, operations.len() as u64);
+            for (_, account) in &accounts {
+                prop_assert_eq!(account.total(), account.available() + account.held());
+                prop_assert!(account.held() >= Decimal::ZERO);
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_channel() {
+        let engine = PaymentEngine::new_with_config(EngineConfig {
+            workers: NonZeroUsize::new(2).unwrap(),
+            channel_capacity: Some(1),
+            ..Default::default()
+        });
+
+        let (accounts, report) = engine
+            .process((1..=100).map(|i| {
+                Operation::from(Transaction::deposit(
+                    ClientId(i % 3 + 1),
+                    TransactionId(u64::from(i)),
+                    1,
+                ))
+            }))
+            .unwrap();
+
+        let total: Decimal = accounts.into_iter().map(|(_, acc)| acc.total()).sum();
+        assert_eq!(total, Decimal::from(100));
+        assert_eq!(report.operations_received, 100);
+        assert_eq!(report.operations_succeeded, 100);
+        assert_eq!(report.processor_stats.len(), 2);
+        assert_eq!(
+            report
+                .processor_stats
+                .iter()
+                .map(|stats| stats.transactions_processed)
+                .sum::<u64>(),
+            100
+        );
+    }
+
+    #[test]
+    fn single_threaded() {
+        // with a single worker the transaction id conflict of two
+        // different clients is always detected
+        let (accounts, report) = PaymentEngine::new_single_threaded()
+            .process([
+                Operation::from(Transaction::deposit(ClientId(1), TransactionId(1), 10)),
+                Operation::from(Transaction::deposit(ClientId(2), TransactionId(1), 10)),
+            ])
+            .unwrap();
+
+        assert_eq!(report.processor_stats.len(), 1);
+        assert_eq!(report.operations_failed, 1);
+        assert_eq!(
+            accounts.into_iter().collect_vec(),
+            vec![(ClientId(1), Account::create(1, 10, 0, false))]
+        );
+    }
+
+    #[test]
+    fn error_mode_skip() {
+        let (accounts, report) = PaymentEngine::new(NonZeroUsize::new(1).unwrap())
+            .process(failing_operations())
+            .unwrap();
+
+        assert_eq!(
+            accounts.into_iter().collect_vec(),
+            vec![(ClientId(1), Account::create(1, 5, 0, false))]
+        );
+        assert_eq!(report.operations_received, 4);
+        assert_eq!(report.operations_succeeded, 2);
+        assert_eq!(report.operations_failed, 2);
+        assert!(report.failures.is_empty());
+    }
+
+    #[test]
+    fn error_mode_collect() {
+        let engine = PaymentEngine::new_with_config(EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Collect,
+            ..Default::default()
+        });
+
+        let (accounts, report) = engine.process(failing_operations()).unwrap();
+
+        assert_eq!(
+            accounts.into_iter().collect_vec(),
+            vec![(ClientId(1), Account::create(1, 5, 0, false))]
+        );
+        assert_eq!(report.operations_failed, 2);
+        assert_eq!(
+            failures(&report),
+            vec![
+                (
+                    1,
+                    &PaymentError::Withdrawal(AccountError::InsufficientAvailable {
+                        needed: 20.into(),
+                        available: 10.into(),
+                        client: ClientId(1)
+                    })
+                ),
+                (
+                    2,
+                    &PaymentError::Transaction(TransactionError::NotFound {
+                        id: TransactionId(5)
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_mode_collect_capped() {
+        let engine = PaymentEngine::new_with_config(EngineConfig {
+            workers: NonZeroUsize::new(4).unwrap(),
+            error_mode: ErrorMode::Collect,
+            max_reported_failures: 3,
+            ..Default::default()
+        });
+
+        let (_, report) = engine
+            .process((0..100).map(|i| {
+                Operation::from(Conflict::dispute(
+                    ClientId(i % 10 + 1),
+                    TransactionId(u64::from(i) + 1),
+                ))
+            }))
+            .unwrap();
+
+        assert_eq!(report.operations_failed, 100);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|(index, _)| *index)
+                .collect_vec(),
+            vec![0, 1, 2]
+        );
+    }
+
+    #[test]
+    fn error_mode_fail() {
+        let engine = PaymentEngine::new_with_config(EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Fail,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            engine.process(failing_operations()).unwrap_err(),
+            PaymentError::Withdrawal(AccountError::InsufficientAvailable {
+                needed: 20.into(),
+                available: 10.into(),
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn unrecoverable_error() {
+        let mut initial = AccountStore::default();
+        initial.insert(Account::from_balance(ClientId(1), Decimal::MAX - Decimal::ONE, 0).unwrap());
+
+        // the overflow aborts the run even though errors are skipped
+        let engine = PaymentEngine::new_with_initial_state(
+            EngineConfig {
+                workers: NonZeroUsize::new(2).unwrap(),
+                error_mode: ErrorMode::Skip,
+                ..Default::default()
+            },
+            initial,
+        );
+        assert_eq!(
+            engine
+                .process([
+                    Operation::from(Transaction::deposit(ClientId(2), TransactionId(1), 10)),
+                    Operation::from(Transaction::deposit(ClientId(1), TransactionId(2), 10)),
+                    Operation::from(Transaction::deposit(ClientId(2), TransactionId(3), 10)),
+                ])
+                .unwrap_err(),
+            PaymentError::Deposit(AccountError::Overflow {
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn report_skipped_rows() {
+        let data = "\
+type, client, tx, amount
+deposit, 1, 1, 10
+deposit, 1, 2, -10
+withdrawal, 1, 3, 20
+unknown, 1, 4, 10
+";
+
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Collect,
+            ..Default::default()
+        };
+        let (_, report) =
+            PaymentEngine::process_reader_with_config(data.as_bytes(), config).unwrap();
+
+        assert_eq!(report.operations_received, 2);
+        assert_eq!(report.operations_succeeded, 1);
+        assert_eq!(report.operations_failed, 1);
+        assert_eq!(report.skipped_rows, 2);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|(index, _)| *index)
+                .collect_vec(),
+            vec![1, 2, 3]
+        );
+
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Fail,
+            ..Default::default()
+        };
+        assert!(PaymentEngine::process_reader_with_config(data.as_bytes(), config).is_err());
+    }
+
+    #[test]
+    fn process_iter() {
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(2).unwrap(),
+            error_mode: ErrorMode::Collect,
+            ..Default::default()
+        };
+        let operations: Vec<Operation> = vec![
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Transaction::deposit(ClientId(2), TransactionId(2), 5).into(),
+            Transaction::withdrawal(ClientId(2), TransactionId(3), 10).into(),
+        ];
+
+        let (accounts, report) = PaymentEngine::process_iter(operations, config).unwrap();
+        assert_eq!(accounts.total_available(), Some(15.into()));
+        assert_eq!(report.operations_failed, 1);
+        assert_eq!(report.failures[0].0, 2);
+    }
+
+    #[test]
+    fn error_sink() {
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(4).unwrap(),
+            ..Default::default()
+        };
+        let operations = (1..=100).flat_map(|client| {
+            [
+                Operation::from(Transaction::deposit(
+                    ClientId(client),
+                    TransactionId(u64::from(client) * 2),
+                    10,
+                )),
+                Operation::from(Transaction::withdrawal(
+                    ClientId(client),
+                    TransactionId(u64::from(client) * 2 + 1),
+                    20,
+                )),
+            ]
+        });
+
+        let mut failed = Vec::new();
+        let (accounts, report) =
+            PaymentEngine::process_with_error_sink_and_config(operations, config, |op, err| {
+                failed.push((op, err))
+            })
+            .unwrap();
+
+        assert_eq!(accounts.len(), 100);
+        assert_eq!(report.operations_failed, 100);
+        assert_eq!(
+            failed
+                .into_iter()
+                .sorted_by_key(|(op, _)| op.client())
+                .collect_vec(),
+            (1..=100)
+                .map(|client| (
+                    Operation::from(Transaction::withdrawal(
+                        ClientId(client),
+                        TransactionId(u64::from(client) * 2 + 1),
+                        20
+                    )),
+                    PaymentError::Withdrawal(AccountError::InsufficientAvailable {
+                        needed: 20.into(),
+                        available: 10.into(),
+                        client: ClientId(client)
+                    })
+                ))
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn shutdown() {
+        let mut engine = PaymentEngine::new(NonZeroUsize::new(4).unwrap());
+        for client in 1..=10 {
+            engine
+                .dispatch(
+                    Transaction::deposit(ClientId(client), TransactionId(u64::from(client)), 10)
+                        .into(),
+                )
+                .unwrap();
+        }
+
+        let accounts = engine.shutdown().unwrap();
+        assert_eq!(accounts.len(), 10);
+        assert_eq!(accounts.total_available(), Some(100.into()));
+    }
+
+    #[test]
+    fn drain_errors() {
+        let mut engine = PaymentEngine::new(NonZeroUsize::new(1).unwrap());
+        engine
+            .dispatch(Transaction::withdrawal(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        assert!(engine.drain_errors().is_empty());
+        engine.shutdown().unwrap();
+
+        let mut engine = PaymentEngine::new_with_error_collection(EngineConfig {
+            workers: NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        });
+        engine
+            .dispatch(Transaction::deposit(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        engine
+            .dispatch(Transaction::withdrawal(ClientId(2), TransactionId(2), 10).into())
+            .unwrap();
+        engine
+            .dispatch(Conflict::resolve(ClientId(1), TransactionId(1)).into())
+            .unwrap();
+
+        let mut errors = Vec::new();
+        let started = std::time::Instant::now();
+        while errors.len() < 2 && started.elapsed() < std::time::Duration::from_secs(5) {
+            errors.extend(engine.drain_errors());
+        }
+        errors.sort_by_key(|err| err.source_client_id());
+        assert_eq!(
+            errors,
+            vec![
+                PaymentError::TransactionNotDisputed {
+                    id: TransactionId(1)
+                },
+                PaymentError::Withdrawal(AccountError::InsufficientAvailable {
+                    needed: 10.into(),
+                    available: 0.into(),
+                    client: ClientId(2)
+                }),
+            ]
+        );
+
+        let accounts = engine.shutdown().unwrap();
+        assert_eq!(accounts.total_available(), Some(10.into()));
+    }
+
+    #[test]
+    fn processing_mode_strict() {
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Collect,
+            processing_mode: ProcessingMode::Strict,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            PaymentEngine::process_iter(failing_operations(), config).unwrap_err(),
+            PaymentError::Withdrawal(AccountError::InsufficientAvailable {
+                needed: 20.into(),
+                available: 10.into(),
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn processing_mode_dry_run() {
+        let mut initial = AccountStore::default();
+        initial.insert(Account::create(1, 5, 0, false));
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(2).unwrap(),
+            processing_mode: ProcessingMode::DryRun,
+            ..Default::default()
+        };
+
+        let (accounts, report) = PaymentEngine::new_with_initial_state(config, initial)
+            .process(failing_operations())
+            .unwrap();
+
+        assert_eq!(
+            accounts.into_sorted_vec(),
+            vec![Account::create(1, 5, 0, false)]
+        );
+        assert_eq!(report.operations_received, 4);
+        assert_eq!(report.operations_failed, 2);
+    }
+
+    #[test]
+    fn progress() {
+        let engine = PaymentEngine::new_with_config(EngineConfig {
+            progress_interval: 10,
+            ..Default::default()
+        });
+        let progress = std::cell::RefCell::new(Vec::new());
+
+        engine
+            .process_with_progress(
+                (1..=35).map(|i| {
+                    Operation::from(Transaction::deposit(ClientId(1), TransactionId(i), 1))
+                }),
+                |dispatched| progress.borrow_mut().push(dispatched),
+            )
+            .unwrap();
+
+        assert_eq!(progress.into_inner(), vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn csv_config() {
+        let config = EngineConfig {
+            csv: CsvConfig {
+                delimiter: b';',
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let data = "\
+type; client; tx; amount
+deposit; 1; 1; 10
+withdrawal; 1; 2; 4
+";
+
+        let (accounts, report) =
+            PaymentEngine::process_reader_with_config(data.as_bytes(), config).unwrap();
+        assert_eq!(accounts.get(ClientId(1)).unwrap().available(), 6.into());
+        assert_eq!(report.skipped_rows, 0);
+    }
+
+    #[test]
+    fn csv_trim_none() {
+        let config = EngineConfig {
+            csv: CsvConfig {
+                trim: csv::Trim::None,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let data = "\
+type,client,tx,amount
+deposit,1,1,10
+deposit, 1, 2, 10
+";
+
+        let (accounts, report) =
+            PaymentEngine::process_reader_with_config(data.as_bytes(), config).unwrap();
+        assert_eq!(accounts.get(ClientId(1)).unwrap().available(), 10.into());
+        assert_eq!(report.skipped_rows, 1);
+    }
+
+    #[test]
+    fn chunks() {
+        let data = "\
+type, client, tx, amount
+deposit, 1, 1, 10
+deposit, 2, 2, 10
+dispute, 1, 1
+dispute, 2, 2
+deposit, 1, 1, 5
+";
+        let process = |mode| {
+            let rows = csv_reader_builder()
+                .from_reader(data.as_bytes())
+                .into_deserialize::<Operation>();
+            let config = EngineConfig {
+                workers: NonZeroUsize::new(2).unwrap(),
+                error_mode: ErrorMode::Collect,
+                ..Default::default()
+            };
+            PaymentEngine::process_rows_filtered(
+                rows,
+                config,
+                AccountStore::default(),
+                |_| true,
+                Some((NonZeroUsize::new(3).unwrap(), mode)),
+            )
+            .unwrap()
+        };
+
+        // the second chunk neither knows the transaction of the dispute
+        // nor the one whose id is reused by the deposit
+        let (accounts, report) = process(ChunkMode::ResetTransactions);
+        assert_eq!(
+            failures(&report),
+            vec![(
+                3,
+                &PaymentError::Transaction(TransactionError::NotFound {
+                    id: TransactionId(2)
+                })
+            )]
+        );
+        assert_eq!(
+            accounts.into_sorted_vec(),
+            vec![
+                Account::create(1, 5, 10, false),
+                Account::create(2, 10, 0, false)
+            ]
+        );
+
+        let (accounts, report) = process(ChunkMode::KeepTransactions);
+        assert_eq!(
+            failures(&report),
+            vec![(
+                4,
+                &PaymentError::Transaction(TransactionError::Conflict {
+                    id: TransactionId(1)
+                })
+            )]
+        );
+        assert_eq!(
+            accounts.into_sorted_vec(),
+            vec![
+                Account::create(1, 0, 10, false),
+                Account::create(2, 0, 10, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn ndjson() {
+        let data = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}
+{"type": "deposit", "client": 1, "tx": 2, "amount":
+
+{"type": "withdrawal", "client": 1, "tx": 3, "amount": 2.5}
+{"type": "dispute", "client": 1, "tx": 1}
+"#;
+
+        let config = EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Collect,
+            ..Default::default()
+        };
+        let (accounts, report) =
+            PaymentEngine::process_ndjson_with_config(data.as_bytes(), config).unwrap();
+
+        assert_eq!(
+            accounts.into_iter().collect_vec(),
+            vec![(
+                ClientId(1),
+                Account::create(1, Decimal::new(-25, 1), 10, false)
+            )]
+        );
+        assert_eq!(report.operations_received, 3);
+        assert_eq!(report.skipped_rows, 1);
+        assert_eq!(
+            report
+                .failures
+                .iter()
+                .map(|(index, _)| *index)
+                .collect_vec(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn worker_count_and_is_running() {
+        let engine = PaymentEngine::new(NonZeroUsize::new(3).unwrap());
+        assert_eq!(engine.worker_count(), 3);
+        assert!(engine.is_running());
+        engine.shutdown().unwrap();
+
+        let mut engine = PaymentEngine::new_with_config(EngineConfig {
+            workers: NonZeroUsize::new(1).unwrap(),
+            error_mode: ErrorMode::Fail,
+            ..Default::default()
+        });
+        engine
+            .dispatch(Transaction::withdrawal(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        let started = std::time::Instant::now();
+        while engine.is_running() && started.elapsed() < std::time::Duration::from_secs(5) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(!engine.is_running());
+        assert!(engine.shutdown().is_err());
+    }
+
+    #[test]
+    fn estimate_memory_bytes() {
+        let mut initial = AccountStore::default();
+        for client in 1..=100 {
+            initial.insert(Account::create(client, 10, 0, false));
+        }
+
+        let mut engine = PaymentEngine::new_with_initial_state(
+            EngineConfig {
+                workers: NonZeroUsize::new(4).unwrap(),
+                ..Default::default()
+            },
+            initial,
+        );
+        assert!(engine.estimate_memory_bytes() >= 100 * size_of::<(ClientId, Account)>());
+
+        engine
+            .dispatch(Transaction::deposit(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        engine.shutdown().unwrap();
+    }
+
+    #[test]
+    fn initial_state() {
+        let mut initial = AccountStore::default();
+        for client in 1..=10 {
+            initial.insert(Account::create(client, 10, 5, client == 1));
+        }
+
+        let engine = PaymentEngine::new_with_initial_state(
+            EngineConfig {
+                workers: NonZeroUsize::new(4).unwrap(),
+                ..Default::default()
+            },
+            initial,
+        );
+
+        let (accounts, report) = engine
+            .process((1..=10).map(|client| {
+                Operation::from(Transaction::withdrawal(
+                    ClientId(client),
+                    TransactionId(u64::from(client)),
+                    10,
+                ))
+            }))
+            .unwrap();
+
+        // the locked account can't withdraw
+        assert_eq!(report.operations_failed, 1);
+        assert_eq!(
+            accounts.into_sorted_vec(),
+            (1..=10)
+                .map(|client| match client {
+                    1 => Account::create(1, 10, 5, true),
+                    _ => Account::create(client, 0, 5, false),
+                })
+                .collect_vec()
+        );
+    }
+
+    fn failures(report: &ProcessingReport) -> Vec<(usize, &PaymentError)> {
+        report
+            .failures
+            .iter()
+            .map(|(index, err)| match err {
+                EngineError::Processing(err) => (*index, err),
+                err => panic!("unexpected error {err:?}"),
+            })
+            .collect_vec()
+    }
+
+    fn failing_operations() -> Vec<Operation> {
+        vec![
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Transaction::withdrawal(ClientId(1), TransactionId(2), 20).into(),
+            Conflict::dispute(ClientId(1), TransactionId(5)).into(),
+            Transaction::withdrawal(ClientId(1), TransactionId(3), 5).into(),
+        ]
+    }
+
     #[test]
     fn dispatch() {
         // first create 10 pairs of sender and receiver to which we can dispatch operations
@@ -131,23 +1444,50 @@ mod test {
         // client and transaction ids, it doesn't matter if the order of the
         // operations makes no sense here as we just want to test that
         // they get dispatched to the correct processor
-        let mut operations = (0..1000)
+        let mut operations = (1..=1000)
             .flat_map(|i| {
                 [
                     (0..10)
-                        .map(|j| Operation::from(Transaction::deposit(i, (i as u32) + j, 1)))
+                        .map(|j| {
+                            Operation::from(Transaction::deposit(
+                                ClientId(i),
+                                TransactionId(u64::from(i) + j),
+                                1,
+                            ))
+                        })
                         .collect_vec(),
                     (0..10)
-                        .map(|j| Operation::from(Transaction::withdrawal(i, (i as u32) + j, 1)))
+                        .map(|j| {
+                            Operation::from(Transaction::withdrawal(
+                                ClientId(i),
+                                TransactionId(u64::from(i) + j),
+                                1,
+                            ))
+                        })
                         .collect_vec(),
                     (0..10)
-                        .map(|j| Operation::from(Conflict::dispute(i, (i as u32) + j)))
+                        .map(|j| {
+                            Operation::from(Conflict::dispute(
+                                ClientId(i),
+                                TransactionId(u64::from(i) + j),
+                            ))
+                        })
                         .collect_vec(),
                     (0..10)
-                        .map(|j| Operation::from(Conflict::resolve(i, (i as u32) + j)))
+                        .map(|j| {
+                            Operation::from(Conflict::resolve(
+                                ClientId(i),
+                                TransactionId(u64::from(i) + j),
+                            ))
+                        })
                         .collect_vec(),
                     (0..10)
-                        .map(|j| Operation::from(Conflict::chargeback(i, (i as u32) + j)))
+                        .map(|j| {
+                            Operation::from(Conflict::chargeback(
+                                ClientId(i),
+                                TransactionId(u64::from(i) + j),
+                            ))
+                        })
                         .collect_vec(),
                 ]
                 .into_iter()
@@ -159,8 +1499,9 @@ mod test {
         operations.shuffle(&mut rand::rng());
 
         // now dispatch all operations
-        for operation in operations {
-            dispatch_operation(operation, &sender).unwrap();
+        let hasher = DispatchHasher::default();
+        for (index, operation) in operations.into_iter().enumerate() {
+            dispatch_operation(index, operation, &sender, &hasher).unwrap();
         }
 
         // drop the sender to ensure the receiver will end after the last dispatched operation
@@ -178,9 +1519,159 @@ mod test {
         }
     }
 
-    fn receive_all_clients(receiver: Receiver<Operation>) -> HashSet<ClientId> {
+    #[test]
+    fn dispatch_deterministic() {
+        // two independently created hashers must dispatch every
+        // client to the same sender
+        let (sender, receiver): (Vec<_>, Vec<_>) = (0..10).map(|_| channel::unbounded()).unzip();
+        let (other_sender, other_receiver): (Vec<_>, Vec<_>) =
+            (0..10).map(|_| channel::unbounded()).unzip();
+
+        for client in 1..=1000 {
+            let operation = Operation::from(Conflict::dispute(ClientId(client), TransactionId(1)));
+            dispatch_operation(0, operation, &sender, &DispatchHasher::default()).unwrap();
+
+            let operation = Operation::from(Conflict::dispute(ClientId(client), TransactionId(1)));
+            dispatch_operation(0, operation, &other_sender, &DispatchHasher::default()).unwrap();
+        }
+
+        std::mem::drop(sender);
+        std::mem::drop(other_sender);
+        assert_eq!(
+            receiver.into_iter().map(receive_all_clients).collect_vec(),
+            other_receiver
+                .into_iter()
+                .map(receive_all_clients)
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn seeded_dispatch() {
+        let assignment = |seed| {
+            let hasher = DispatchHasher::seeded(seed);
+            (1..=100)
+                .map(|client| hasher.worker_index(ClientId(client), NonZeroUsize::new(4).unwrap()))
+                .collect_vec()
+        };
+        assert_eq!(assignment(42), assignment(42));
+        assert_ne!(assignment(42), assignment(43));
+        // the upper bits of the seed are used as well
+        assert_ne!(assignment(42), assignment(42 | 1 << 32));
+
+        // each processor manages exactly the clients assigned to it
+        let (_, report) = PaymentEngine::new_deterministic(NonZeroUsize::new(4).unwrap(), 42)
+            .process((1..=100).map(|client| {
+                Operation::from(Transaction::deposit(
+                    ClientId(client),
+                    TransactionId(u64::from(client)),
+                    10,
+                ))
+            }))
+            .unwrap();
+        let assignment = assignment(42);
+        assert_eq!(
+            report
+                .processor_stats
+                .iter()
+                .map(|stats| stats.accounts_managed)
+                .collect_vec(),
+            (0..4)
+                .map(|worker| assignment.iter().filter(|index| **index == worker).count())
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn custom_dispatch_hasher() {
+        // a hasher that dispatches all clients to the same worker
+        #[derive(Default)]
+        struct ConstantHasher;
+
+        impl std::hash::Hasher for ConstantHasher {
+            fn finish(&self) -> u64 {
+                0
+            }
+
+            fn write(&mut self, _: &[u8]) {}
+        }
+
+        let (sender, receiver): (Vec<_>, Vec<_>) = (0..3).map(|_| channel::unbounded()).unzip();
+        let hasher =
+            DispatchHasher::new(std::hash::BuildHasherDefault::<ConstantHasher>::default());
+        for client in 1..=100 {
+            let operation = Operation::from(Conflict::dispute(ClientId(client), TransactionId(1)));
+            dispatch_operation(0, operation, &sender, &hasher).unwrap();
+        }
+
+        std::mem::drop(sender);
+        let sets = receiver.into_iter().map(receive_all_clients).collect_vec();
+        assert_eq!(sets[0].len(), 100);
+        assert!(sets[1].is_empty());
+        assert!(sets[2].is_empty());
+
+        let (_, report) = PaymentEngine::new_with_hash_dispatch(
+            NonZeroUsize::new(3).unwrap(),
+            std::hash::BuildHasherDefault::<ConstantHasher>::default(),
+        )
+        .process((1..=10).map(|client| {
+            Operation::from(Transaction::deposit(
+                ClientId(client),
+                TransactionId(u64::from(client)),
+                10,
+            ))
+        }))
+        .unwrap();
+        assert_eq!(
+            report
+                .processor_stats
+                .iter()
+                .map(|stats| stats.accounts_managed)
+                .collect_vec(),
+            vec![10, 0, 0]
+        );
+    }
+
+    #[test]
+    fn custom_dispatch() {
+        let operations = (1..=10)
+            .map(|client| {
+                Operation::from(Transaction::deposit(
+                    ClientId(client),
+                    TransactionId(u64::from(client)),
+                    10,
+                ))
+            })
+            .collect_vec();
+
+        // out of range indices are taken modulo the number of workers
+        let (accounts, report) =
+            PaymentEngine::new_with_custom_dispatch(NonZeroUsize::new(3).unwrap(), |_, workers| {
+                workers + 2
+            })
+            .process(operations.clone())
+            .unwrap();
+        assert_eq!(accounts.len(), 10);
+        assert_eq!(
+            report
+                .processor_stats
+                .iter()
+                .map(|stats| stats.accounts_managed)
+                .collect_vec(),
+            vec![0, 0, 10]
+        );
+
+        let (accounts, _) =
+            PaymentEngine::process_with_worker_assignment(operations, |client, workers| {
+                client.get() as usize % workers
+            })
+            .unwrap();
+        assert_eq!(accounts.total_available(), Some(100.into()));
+    }
+
+    fn receive_all_clients(receiver: Receiver<ProcessorMessage>) -> HashSet<ClientId> {
         let mut clients = HashSet::new();
-        while let Ok(operation) = receiver.recv() {
+        while let Ok(ProcessorMessage::Operation(_, operation)) = receiver.recv() {
             clients.insert(operation.client());
         }
 
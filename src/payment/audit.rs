@@ -0,0 +1,14 @@
+use std::time::SystemTime;
+
+use crate::operation::Operation;
+
+use super::PaymentResult;
+
+/// Record of a single operation processed by a `PaymentProcessor`.
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub operation: Operation,
+    /// Point in time the operation was processed.
+    pub timestamp: SystemTime,
+    pub result: PaymentResult<()>,
+}
@@ -0,0 +1,79 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{Account, ClientId},
+    operation::{DisputeState, Transaction, TransactionId, TransactionStoreValue, TransactionType},
+};
+
+/// State of a `PaymentProcessor` which can be written to disk, e.g. as JSON,
+/// to continue processing after a crash, see `PaymentProcessor::checkpoint`.
+/// Accounts and transactions are sorted by their id.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointData {
+    pub accounts: Vec<AccountSummary>,
+    pub transactions: Vec<StoredTransaction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub client: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredTransaction {
+    #[serde(rename = "type")]
+    pub type_: TransactionType,
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub amount: Decimal,
+    pub dispute_state: DisputeState,
+}
+
+impl From<&Account> for AccountSummary {
+    fn from(account: &Account) -> Self {
+        Self {
+            client: account.client(),
+            available: account.available(),
+            held: account.held(),
+            locked: account.is_locked(),
+        }
+    }
+}
+
+impl From<AccountSummary> for Account {
+    fn from(summary: AccountSummary) -> Self {
+        Account::restore(
+            summary.client,
+            summary.available,
+            summary.held,
+            summary.locked,
+        )
+    }
+}
+
+impl From<&TransactionStoreValue> for StoredTransaction {
+    fn from(value: &TransactionStoreValue) -> Self {
+        Self {
+            type_: value.transaction.type_,
+            client: value.transaction.client,
+            tx: value.transaction.tx,
+            amount: value.transaction.amount,
+            dispute_state: value.dispute_state,
+        }
+    }
+}
+
+impl From<StoredTransaction> for Transaction {
+    fn from(stored: StoredTransaction) -> Self {
+        Transaction {
+            type_: stored.type_,
+            client: stored.client,
+            tx: stored.tx,
+            amount: stored.amount,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fmt::{self, Debug},
+    hash::BuildHasher,
+    num::NonZeroUsize,
+    sync::Arc,
+};
+
+use rustc_hash::FxBuildHasher;
+
+use crate::{CsvConfig, account::ClientId};
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Number of `PaymentProcessor` worker threads.
+    pub workers: NonZeroUsize,
+    /// Capacity of the channel of each worker. `None` creates unbounded
+    /// channels, `Some(n)` creates bounded channels which block the
+    /// dispatching thread as soon as a worker falls `n` operations behind.
+    pub channel_capacity: Option<usize>,
+    /// How errors of single operations are handled.
+    pub error_mode: ErrorMode,
+    /// Whether errors abort the run and whether account changes are kept.
+    pub processing_mode: ProcessingMode,
+    /// Maximal number of failures recorded in the `ProcessingReport`.
+    pub max_reported_failures: usize,
+    /// Number of dispatched operations between two calls of the progress
+    /// callback of `PaymentEngine::process_with_progress`.
+    pub progress_interval: u64,
+    /// Assigns the operations to the workers by their client id.
+    pub dispatch_hasher: DispatchHasher,
+    /// Settings of the CSV reader used by the CSV entry points.
+    pub csv: CsvConfig,
+    /// Maximal number of transactions each worker stores. `None` stores all
+    /// transactions, `Some(n)` stores them in a `BoundedTransactionStore`
+    /// which evicts the least recently used transaction that isn't disputed.
+    /// Conflicts referencing an evicted transaction fail as if it was
+    /// unknown and its id is no longer detected as a duplicate.
+    pub max_stored_transactions: Option<NonZeroUsize>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+            channel_capacity: None,
+            error_mode: ErrorMode::default(),
+            processing_mode: ProcessingMode::default(),
+            max_reported_failures: 100,
+            progress_interval: 1000,
+            dispatch_hasher: DispatchHasher::default(),
+            csv: CsvConfig::default(),
+            max_stored_transactions: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    // The error mode that actually applies, the strict
+    // processing mode always fails on the first error.
+    pub(crate) fn effective_error_mode(&self) -> ErrorMode {
+        match self.processing_mode {
+            ProcessingMode::Strict => ErrorMode::Fail,
+            ProcessingMode::Lenient | ProcessingMode::DryRun => self.error_mode,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingMode {
+    /// Processing is aborted on the first failed operation or malformed
+    /// input row, independent of the `ErrorMode`.
+    Strict,
+    /// Errors are handled according to the `ErrorMode`.
+    #[default]
+    Lenient,
+    /// Like `Lenient`, but all account changes are discarded. The returned
+    /// accounts are the initial ones and only the `ProcessingReport` tells
+    /// what would have happened, e.g. to validate a file before processing it.
+    DryRun,
+}
+
+/// How `PaymentEngine::process_csv_chunked` handles the transactions of
+/// previous chunks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkMode {
+    /// All chunks share the transactions, so conflicts can reference
+    /// transactions of previous chunks. The memory used for transactions
+    /// grows with the whole file, the same as without chunks.
+    #[default]
+    KeepTransactions,
+    /// Only the accounts are kept between chunks, the transactions are
+    /// dropped. This bounds the memory used for transactions by the chunk
+    /// size, but conflicts referencing transactions of previous chunks fail.
+    ResetTransactions,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Failed operations and malformed input rows are ignored
+    /// and only counted in the `ProcessingReport`.
+    #[default]
+    Skip,
+    /// Failed operations and malformed input rows are ignored
+    /// but recorded in the `ProcessingReport`.
+    Collect,
+    /// Processing is aborted on the first failed operation or malformed
+    /// input row.
+    Fail,
+}
+
+/// Assigns client ids to the worker their operations are dispatched to.
+///
+/// The default hashes the client id with `FxHasher`, which is deterministic,
+/// so the same client is always dispatched to the same worker independent of
+/// the process or the Rust version. Any other `BuildHasher` can be plugged in
+/// via `new`, a completely custom assignment via `custom`.
+#[derive(Clone)]
+pub struct DispatchHasher(Arc<dyn Fn(ClientId, usize) -> usize + Send + Sync>);
+
+impl DispatchHasher {
+    pub fn new<S>(build_hasher: S) -> Self
+    where
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        Self(Arc::new(move |client, workers| {
+            (build_hasher.hash_one(client) % (workers as u64)) as usize
+        }))
+    }
+
+    /// Hashes the client ids together with the whole `seed` with `FxHasher`.
+    /// The same seed always results in the same assignment of clients to
+    /// workers, e.g. to replay a run with the dispatching of a production run.
+    pub fn seeded(seed: u64) -> Self {
+        Self(Arc::new(move |client, workers| {
+            (FxBuildHasher.hash_one((seed, client)) % (workers as u64)) as usize
+        }))
+    }
+
+    /// Uses `assign` to map a client id and the number of workers to the
+    /// index of a worker, e.g. to route clients by a shard of an external
+    /// database. `assign` must always return the same worker for the same
+    /// client, otherwise the order of its operations is lost. Indices out of
+    /// range are taken modulo the number of workers.
+    pub fn custom<F>(assign: F) -> Self
+    where
+        F: Fn(ClientId, usize) -> usize + Send + Sync + 'static,
+    {
+        Self(Arc::new(assign))
+    }
+
+    /// Index of the worker out of `workers` the client is dispatched to.
+    pub fn worker_index(&self, client: ClientId, workers: NonZeroUsize) -> usize {
+        (self.0)(client, workers.get()) % workers
+    }
+}
+
+impl Default for DispatchHasher {
+    fn default() -> Self {
+        Self::new(FxBuildHasher)
+    }
+}
+
+impl Debug for DispatchHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DispatchHasher").finish_non_exhaustive()
+    }
+}
@@ -7,10 +7,28 @@ use crate::{
 
 pub type PaymentResult<T> = Result<T, PaymentError>;
 
-#[derive(Error, Debug, PartialEq, Eq)]
+pub type EngineResult<T> = Result<T, EngineError>;
+
+/// Errors of the public entry points which read their input, e.g. from a
+/// file, in addition to the processing itself.
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Processing(#[from] PaymentError),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum PaymentError {
     #[error("transaction '{id}' already disputed")]
     TransactionAlreadyDisputed { id: TransactionId },
+    #[error("transaction '{id}' already charged back")]
+    TransactionAlreadyChargedBack { id: TransactionId },
     #[error("transaction '{id}' not disputed")]
     TransactionNotDisputed { id: TransactionId },
     #[error(
@@ -23,6 +41,8 @@ pub enum PaymentError {
     },
     #[error("transaction '{tx}' cannot be disputed because its a withdrawal ")]
     WithdrawalCannotBeDisputed { tx: TransactionId },
+    #[error("account for client '{client}' not found")]
+    UnknownClient { client: ClientId },
 
     #[error("deposit failed")]
     Deposit(#[source] AccountError),
@@ -34,6 +54,8 @@ pub enum PaymentError {
     Release(#[source] AccountError),
     #[error("chargeback failed")]
     Chargeback(#[source] AccountError),
+    #[error("merging accounts failed")]
+    Merge(#[source] AccountError),
 
     #[error(transparent)]
     Transaction(#[from] TransactionError),
@@ -42,4 +64,212 @@ pub enum PaymentError {
     DispatchOperation { client: ClientId },
     #[error("failed to join payment processors")]
     JoiningProcessors,
+    #[error("account for client '{client}' exists more than once")]
+    DuplicateAccount { client: ClientId },
+}
+
+impl PaymentError {
+    /// Returns `true` for infrastructure failures which should abort the
+    /// whole run, in contrast to rejected operations which can be skipped.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            PaymentError::DispatchOperation { .. }
+                | PaymentError::JoiningProcessors
+                | PaymentError::DuplicateAccount { .. }
+        )
+    }
+
+    /// Returns `true` for rejected operations, e.g. a dispute of an already
+    /// disputed transaction, after which processing can continue. Fatal
+    /// errors and balances which would overflow aren't recoverable, the
+    /// latter as the account can't take any further funds.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            PaymentError::Deposit(err)
+            | PaymentError::Withdrawal(err)
+            | PaymentError::Hold(err)
+            | PaymentError::Release(err)
+            | PaymentError::Chargeback(err)
+            | PaymentError::Merge(err) => !matches!(err, AccountError::Overflow { .. }),
+            err => !err.is_fatal(),
+        }
+    }
+
+    /// The id of the transaction the failed operation referenced, if the
+    /// error carries it.
+    pub fn source_transaction_id(&self) -> Option<TransactionId> {
+        match self {
+            PaymentError::TransactionAlreadyDisputed { id }
+            | PaymentError::TransactionAlreadyChargedBack { id }
+            | PaymentError::TransactionNotDisputed { id } => Some(*id),
+            PaymentError::ConflictClientMismatch { tx, .. }
+            | PaymentError::WithdrawalCannotBeDisputed { tx } => Some(*tx),
+            PaymentError::Transaction(err) => match err {
+                TransactionError::NotFound { id }
+                | TransactionError::Conflict { id }
+                | TransactionError::DuplicateClientId { id, .. }
+                | TransactionError::CapacityExceeded { id }
+                | TransactionError::DeserializeMissingAmount { id, .. }
+                | TransactionError::DeserializeUnknownType { id, .. }
+                | TransactionError::InvalidAmount { id, .. } => Some(*id),
+                TransactionError::InvalidTransactionId { .. }
+                | TransactionError::MalformedRow { .. } => None,
+            },
+            PaymentError::UnknownClient { .. }
+            | PaymentError::Deposit(_)
+            | PaymentError::Withdrawal(_)
+            | PaymentError::Hold(_)
+            | PaymentError::Release(_)
+            | PaymentError::Chargeback(_)
+            | PaymentError::Merge(_)
+            | PaymentError::DispatchOperation { .. }
+            | PaymentError::JoiningProcessors
+            | PaymentError::DuplicateAccount { .. } => None,
+        }
+    }
+
+    /// The id of the client of the failed operation, if the error carries it.
+    pub fn source_client_id(&self) -> Option<ClientId> {
+        match self {
+            PaymentError::ConflictClientMismatch { actual, .. } => Some(*actual),
+            PaymentError::UnknownClient { client }
+            | PaymentError::DispatchOperation { client }
+            | PaymentError::DuplicateAccount { client } => Some(*client),
+            PaymentError::Deposit(err)
+            | PaymentError::Withdrawal(err)
+            | PaymentError::Hold(err)
+            | PaymentError::Release(err)
+            | PaymentError::Chargeback(err)
+            | PaymentError::Merge(err) => match err {
+                AccountError::InsufficientAvailable { client, .. }
+                | AccountError::InsufficientHeld { client, .. }
+                | AccountError::Overflow { client }
+                | AccountError::Locked { client }
+                | AccountError::TotalMismatch { client, .. } => Some(*client),
+                AccountError::InvalidClientId { .. } => None,
+            },
+            PaymentError::Transaction(TransactionError::DuplicateClientId {
+                new_client, ..
+            }) => Some(*new_client),
+            PaymentError::TransactionAlreadyDisputed { .. }
+            | PaymentError::TransactionAlreadyChargedBack { .. }
+            | PaymentError::TransactionNotDisputed { .. }
+            | PaymentError::WithdrawalCannotBeDisputed { .. }
+            | PaymentError::Transaction(_)
+            | PaymentError::JoiningProcessors => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_fatal() {
+        assert!(PaymentError::JoiningProcessors.is_fatal());
+        assert!(
+            PaymentError::DuplicateAccount {
+                client: ClientId(1)
+            }
+            .is_fatal()
+        );
+        assert!(
+            PaymentError::DispatchOperation {
+                client: ClientId(1)
+            }
+            .is_fatal()
+        );
+
+        assert!(
+            !PaymentError::WithdrawalCannotBeDisputed {
+                tx: TransactionId(1)
+            }
+            .is_fatal()
+        );
+        assert!(
+            !PaymentError::TransactionAlreadyDisputed {
+                id: TransactionId(1)
+            }
+            .is_fatal()
+        );
+        assert!(
+            !PaymentError::Withdrawal(AccountError::Locked {
+                client: ClientId(1)
+            })
+            .is_fatal()
+        );
+        assert!(
+            !PaymentError::Transaction(TransactionError::NotFound {
+                id: TransactionId(1)
+            })
+            .is_fatal()
+        );
+    }
+
+    #[test]
+    fn is_recoverable() {
+        assert!(!PaymentError::JoiningProcessors.is_recoverable());
+        assert!(
+            !PaymentError::DispatchOperation {
+                client: ClientId(1)
+            }
+            .is_recoverable()
+        );
+
+        assert!(
+            PaymentError::TransactionAlreadyDisputed {
+                id: TransactionId(1)
+            }
+            .is_recoverable()
+        );
+        assert!(
+            !PaymentError::Deposit(AccountError::Overflow {
+                client: ClientId(1)
+            })
+            .is_recoverable()
+        );
+        assert!(
+            !PaymentError::Hold(AccountError::Overflow {
+                client: ClientId(1)
+            })
+            .is_recoverable()
+        );
+        assert!(
+            PaymentError::Withdrawal(AccountError::Locked {
+                client: ClientId(1)
+            })
+            .is_recoverable()
+        );
+    }
+
+    #[test]
+    fn source_ids() {
+        let err = PaymentError::ConflictClientMismatch {
+            tx: TransactionId(1),
+            expected: ClientId(2),
+            actual: ClientId(3),
+        };
+        assert_eq!(err.source_transaction_id(), Some(TransactionId(1)));
+        assert_eq!(err.source_client_id(), Some(ClientId(3)));
+
+        let err = PaymentError::Withdrawal(AccountError::Locked {
+            client: ClientId(1),
+        });
+        assert_eq!(err.source_transaction_id(), None);
+        assert_eq!(err.source_client_id(), Some(ClientId(1)));
+
+        let err = PaymentError::Transaction(TransactionError::NotFound {
+            id: TransactionId(4),
+        });
+        assert_eq!(err.source_transaction_id(), Some(TransactionId(4)));
+        assert_eq!(err.source_client_id(), None);
+
+        assert_eq!(
+            PaymentError::JoiningProcessors.source_transaction_id(),
+            None
+        );
+        assert_eq!(PaymentError::JoiningProcessors.source_client_id(), None);
+    }
 }
@@ -1,18 +1,96 @@
-use crossbeam::channel::Receiver;
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use crossbeam::channel::{Receiver, Sender};
 
 use crate::{
-    account::AccountStore,
+    account::{Account, AccountError, AccountStore, ClientId},
     operation::{
-        Conflict, ConflictType, Operation, Transaction, TransactionStore, TransactionType,
+        BoundedLockForInsert, BoundedTransactionStore, Conflict, ConflictType, DisputeState,
+        LockForInsert, Operation, Transaction, TransactionId, TransactionResult, TransactionStore,
+        TransactionStoreValue, TransactionStoreValueMut, TransactionType,
     },
 };
 
-use super::{PaymentError, PaymentResult};
+#[cfg(feature = "audit_log")]
+use super::AuditEntry;
+use super::{
+    AccountSummary, CheckpointData, ChunkMode, EngineConfig, ErrorMode, PaymentError,
+    PaymentResult, ProcessingMode, ProcessingReport, StoredTransaction,
+};
+
+/// Messages a `PaymentProcessor` receives in `run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessorMessage {
+    /// An operation together with the index of its input row, which
+    /// identifies the operation in the `ProcessingReport`.
+    Operation(usize, Operation),
+    /// All operations of the current chunk were received, see
+    /// `PaymentEngine::process_csv_chunked`. With
+    /// `ChunkMode::ResetTransactions` the stored transactions are dropped.
+    EndOfChunk(ChunkMode),
+}
 
+/// Processes the operations of a set of clients.
 #[derive(Default)]
 pub struct PaymentProcessor {
     accounts: AccountStore,
-    transactions: TransactionStore,
+    transactions: Transactions,
+    error_mode: ErrorMode,
+    dry_run: bool,
+    max_reported_failures: usize,
+    stats: ProcessorStats,
+    // receives a copy of every failed operation together with its error
+    error_sink: Option<Sender<(Operation, PaymentError)>>,
+    // updated with `estimate_memory_bytes` after every operation
+    memory_estimate: Option<Arc<AtomicUsize>>,
+    #[cfg(feature = "audit_log")]
+    audit_log: Vec<AuditEntry>,
+    // index of the worker thread running the processor, used to
+    // correlate the spans of the different workers
+    #[cfg(feature = "tracing")]
+    worker: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessorStats {
+    /// Number of processed deposits and withdrawals, including failed ones.
+    pub transactions_processed: u64,
+    /// Number of processed disputes, resolves and chargebacks, including failed ones.
+    pub conflicts_processed: u64,
+    /// Number of operations that failed.
+    pub errors: u64,
+    /// Number of client accounts owned by the processor.
+    pub accounts_managed: usize,
+    /// Number of disputes which are neither resolved nor charged back.
+    pub disputes_in_flight: usize,
+}
+
+/// A clone forks the current state, e.g. to compare the outcome of different
+/// operations. It reports its failures to the same error sink but publishes
+/// its memory estimate to a counter of its own.
+impl Clone for PaymentProcessor {
+    fn clone(&self) -> Self {
+        Self {
+            accounts: self.accounts.clone(),
+            transactions: self.transactions.clone(),
+            error_mode: self.error_mode,
+            dry_run: self.dry_run,
+            max_reported_failures: self.max_reported_failures,
+            stats: self.stats,
+            error_sink: self.error_sink.clone(),
+            memory_estimate: self
+                .memory_estimate
+                .as_ref()
+                .map(|estimate| Arc::new(AtomicUsize::new(estimate.load(Ordering::Relaxed)))),
+            #[cfg(feature = "audit_log")]
+            audit_log: self.audit_log.clone(),
+            #[cfg(feature = "tracing")]
+            worker: self.worker,
+        }
+    }
 }
 
 impl PaymentProcessor {
@@ -20,29 +98,309 @@ impl PaymentProcessor {
         Default::default()
     }
 
-    pub fn run(mut self, receiver: Receiver<Operation>) -> PaymentResult<AccountStore> {
-        while let Ok(operation) = receiver.recv() {
-            if self.process(operation).is_err() {
-                // The current processing scheme is designed to
-                // ignore errors and continue processing.
+    /// Creates a processor with pre-populated stores, e.g. to continue
+    /// processing where a previous processor stopped. Conflicts can then
+    /// reference the given transactions.
+    pub fn new_with_stores(accounts: AccountStore, transactions: TransactionStore) -> Self {
+        Self {
+            accounts,
+            transactions: Transactions::Unbounded(transactions),
+            ..Default::default()
+        }
+    }
+
+    /// Restores a processor from a checkpoint created with `checkpoint`.
+    /// Fails if the checkpoint contains a transaction id more than once.
+    pub fn from_checkpoint(data: CheckpointData) -> PaymentResult<Self> {
+        let mut accounts = AccountStore::default();
+        accounts.extend_from_iter(data.accounts.into_iter().map(Account::from));
+
+        let mut transactions = TransactionStore::default();
+        for stored in data.transactions {
+            transactions
+                .lock_for_insert(Transaction::from(stored))?
+                .finish();
+            *transactions.get_mut(stored.tx)?.dispute_state = stored.dispute_state;
+        }
+
+        Ok(Self::new_with_stores(accounts, transactions))
+    }
+
+    pub fn with_config(config: &EngineConfig) -> Self {
+        Self::with_state(config, AccountStore::default())
+    }
+
+    pub fn with_state(config: &EngineConfig, accounts: AccountStore) -> Self {
+        let transactions = match config.max_stored_transactions {
+            Some(max_entries) => Transactions::Bounded(BoundedTransactionStore::new(max_entries)),
+            None => Transactions::default(),
+        };
+
+        Self {
+            accounts,
+            transactions,
+            error_mode: config.effective_error_mode(),
+            dry_run: config.processing_mode == ProcessingMode::DryRun,
+            max_reported_failures: config.max_reported_failures,
+            ..Default::default()
+        }
+    }
+
+    // Sets the index of the worker thread the processor runs on.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn with_worker(self, worker: usize) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            worker,
+            ..self
+        }
+    }
+
+    // Sets the channel every failed operation is sent to.
+    pub(crate) fn with_error_sink(self, error_sink: Sender<(Operation, PaymentError)>) -> Self {
+        Self {
+            error_sink: Some(error_sink),
+            ..self
+        }
+    }
+
+    // Sets the counter the memory estimate is published to, it's
+    // initialized with the current estimate.
+    pub(crate) fn with_memory_estimate(self, memory_estimate: Arc<AtomicUsize>) -> Self {
+        memory_estimate.store(self.estimate_memory_bytes(), Ordering::Relaxed);
+        Self {
+            memory_estimate: Some(memory_estimate),
+            ..self
+        }
+    }
+
+    /// Processes the operations of the receiver until it's disconnected.
+    /// Errors which aren't recoverable abort the run independent of the
+    /// error mode, all others are handled according to the error mode.
+    pub fn run(
+        mut self,
+        receiver: Receiver<ProcessorMessage>,
+    ) -> PaymentResult<(AccountStore, ProcessingReport)> {
+        // in a dry run the changes are applied as usual to detect all
+        // failures but the initial accounts are returned
+        let initial = self.dry_run.then(|| self.accounts.clone());
+
+        let mut failures = Vec::new();
+        while let Ok(message) = receiver.recv() {
+            let (index, operation) = match message {
+                ProcessorMessage::Operation(index, operation) => (index, operation),
+                ProcessorMessage::EndOfChunk(ChunkMode::KeepTransactions) => continue,
+                ProcessorMessage::EndOfChunk(ChunkMode::ResetTransactions) => {
+                    self.transactions.clear();
+                    continue;
+                }
+            };
+
+            let result = self.process(operation);
+            if let Some(memory_estimate) = &self.memory_estimate {
+                memory_estimate.store(self.estimate_memory_bytes(), Ordering::Relaxed);
+            }
+
+            let Err(err) = result else {
+                continue;
+            };
+
+            if let Some(sink) = &self.error_sink {
+                // the receiving side only stops listening if the engine
+                // gave up on the run, so we don't care about the result
+                let _ = sink.send((operation, err.clone()));
+            }
+
+            if !err.is_recoverable() {
+                return Err(err);
+            }
+
+            match self.error_mode {
+                // The skip mode is designed to ignore
+                // errors and continue processing.
+                ErrorMode::Skip => {}
+                ErrorMode::Collect => {
+                    if failures.len() < self.max_reported_failures {
+                        failures.push((index, err.into()));
+                    }
+                }
+                ErrorMode::Fail => return Err(err),
             }
         }
 
-        Ok(self.accounts)
+        let stats = self.stats();
+        let received = stats.transactions_processed + stats.conflicts_processed;
+        let report = ProcessingReport {
+            operations_received: received,
+            operations_succeeded: received - stats.errors,
+            operations_failed: stats.errors,
+            skipped_rows: 0,
+            failures,
+            processor_stats: vec![stats],
+            #[cfg(feature = "audit_log")]
+            audit_log: self.audit_log,
+        };
+
+        Ok((initial.unwrap_or(self.accounts), report))
+    }
+
+    /// Processes the given operations synchronously on the calling thread and
+    /// returns the result of each operation in the order of the input. The
+    /// error mode of the processor doesn't apply, all errors are returned.
+    pub fn process_batch(&mut self, operations: &[Operation]) -> Vec<PaymentResult<()>> {
+        operations
+            .iter()
+            .map(|operation| self.process(*operation))
+            .collect()
+    }
+
+    pub fn stats(&self) -> ProcessorStats {
+        ProcessorStats {
+            accounts_managed: self.accounts.len(),
+            disputes_in_flight: self.disputes_in_flight(),
+            ..self.stats
+        }
+    }
+
+    /// Every operation processed so far together with its result.
+    #[cfg(feature = "audit_log")]
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        &self.audit_log
+    }
+
+    /// Number of distinct client accounts the processor has seen.
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Number of successfully inserted transactions.
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Number of disputes which are neither resolved nor charged back. This
+    /// iterates all transactions, so it's meant for periodic polling. The
+    /// counts of all workers are part of `ProcessingReport::processor_stats`.
+    pub fn disputes_in_flight(&self) -> usize {
+        self.transactions
+            .iter()
+            .filter(|value| value.dispute_state == DisputeState::Disputed)
+            .count()
+    }
+
+    /// Approximate heap usage of the account and transaction store in
+    /// bytes, see `AccountStore::estimate_memory_bytes`.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        self.accounts.estimate_memory_bytes() + self.transactions.estimate_memory_bytes()
+    }
+
+    /// Snapshot of all accounts and transactions, which can be serialized
+    /// and later restored with `from_checkpoint`.
+    pub fn checkpoint(&self) -> CheckpointData {
+        let mut transactions = self
+            .transactions
+            .iter()
+            .map(StoredTransaction::from)
+            .collect::<Vec<_>>();
+        transactions.sort_unstable_by_key(|stored| stored.tx);
+
+        CheckpointData {
+            accounts: self
+                .accounts
+                .sorted_iter()
+                .map(AccountSummary::from)
+                .collect(),
+            transactions,
+        }
+    }
+
+    /// Removes all accounts, transactions and statistics so the processor
+    /// can be reused without reallocating its stores. The configuration,
+    /// i.e. the error mode, dry run, failure cap and error sink, is kept
+    /// and the memory estimate is updated to the emptied stores.
+    pub fn reset(&mut self) {
+        self.accounts.clear();
+        self.transactions.clear();
+        self.stats = ProcessorStats::default();
+        #[cfg(feature = "audit_log")]
+        self.audit_log.clear();
+        if let Some(memory_estimate) = &self.memory_estimate {
+            memory_estimate.store(self.estimate_memory_bytes(), Ordering::Relaxed);
+        }
     }
 
-    #[allow(unused)]
     pub fn accounts(&self) -> &AccountStore {
         &self.accounts
     }
 
+    /// Seeds the processor with the given account, replacing
+    /// an existing account of the same client.
+    pub fn inject_account(&mut self, account: Account) {
+        self.accounts.insert(account);
+    }
+
+    /// Administrative reversal of the lock set by a chargeback.
+    pub fn unlock_account(&mut self, client: ClientId) -> PaymentResult<()> {
+        if !self.accounts.contains_client(client) {
+            return Err(PaymentError::UnknownClient { client });
+        }
+
+        self.accounts.get_mut(client).unlock();
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                worker = self.worker,
+                client_id = operation.client().get(),
+                tx_id = operation.transaction_id().get(),
+            ),
+            err(level = "debug"),
+        )
+    )]
     fn process(&mut self, operation: Operation) -> PaymentResult<()> {
-        match operation {
-            Operation::Transaction(tx) => self.transaction(tx),
-            Operation::Conflict(dispute) => self.conflict(dispute),
+        let res = match operation {
+            Operation::Transaction(tx) => {
+                self.stats.transactions_processed += 1;
+                self.transaction(tx)
+            }
+            Operation::Conflict(dispute) => {
+                self.stats.conflicts_processed += 1;
+                self.conflict(dispute)
+            }
+        };
+
+        if res.is_err() {
+            self.stats.errors += 1;
         }
+
+        #[cfg(feature = "audit_log")]
+        self.audit_log.push(AuditEntry {
+            operation,
+            timestamp: std::time::SystemTime::now(),
+            result: res.clone(),
+        });
+
+        res
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                worker = self.worker,
+                client_id = tx.client.get(),
+                tx_id = tx.tx.get(),
+                operation_type = %tx.type_,
+            ),
+        )
+    )]
     fn transaction(&mut self, tx: Transaction) -> PaymentResult<()> {
         // We first lock the slot for the transaction in the trasnaction
         // store to ensure there is not already a transaction with the
@@ -65,6 +423,19 @@ impl PaymentProcessor {
         Ok(())
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                worker = self.worker,
+                client_id = conflict.client.get(),
+                tx_id = conflict.tx.get(),
+                operation_type = %conflict.type_,
+            ),
+        )
+    )]
     fn conflict(&mut self, conflict: Conflict) -> PaymentResult<()> {
         let target = self.transactions.get_mut(conflict.tx)?;
 
@@ -82,68 +453,136 @@ impl PaymentProcessor {
             return Err(PaymentError::WithdrawalCannotBeDisputed { tx });
         }
 
-        match conflict.type_ {
-            ConflictType::Dispute => {
-                if target.disputed {
-                    return Err(PaymentError::TransactionAlreadyDisputed { id: tx });
-                }
-
-                self.accounts
-                    .get_mut(client)
-                    .dispute(amount)
-                    .map_err(PaymentError::Hold)?;
-                target.disputed = true;
-            }
-            ConflictType::Resolve => {
-                if !target.disputed {
+        // check the dispute state of the transaction first so the
+        // account is only touched if the transition is allowed
+        let (next_state, account_error): (_, fn(AccountError) -> PaymentError) =
+            match conflict.type_ {
+                ConflictType::Dispute => match *target.dispute_state {
+                    DisputeState::Clean | DisputeState::Resolved => {
+                        (DisputeState::Disputed, PaymentError::Hold)
+                    }
+                    DisputeState::Disputed => {
+                        return Err(PaymentError::TransactionAlreadyDisputed { id: tx });
+                    }
+                    DisputeState::Chargedback => {
+                        return Err(PaymentError::TransactionAlreadyChargedBack { id: tx });
+                    }
+                },
+                ConflictType::Resolve | ConflictType::Chargeback
+                    if *target.dispute_state != DisputeState::Disputed =>
+                {
                     return Err(PaymentError::TransactionNotDisputed { id: tx });
                 }
+                ConflictType::Resolve => (DisputeState::Resolved, PaymentError::Release),
+                ConflictType::Chargeback => (DisputeState::Chargedback, PaymentError::Chargeback),
+            };
 
-                self.accounts
-                    .get_mut(client)
-                    .release(amount)
-                    .map_err(PaymentError::Release)?;
-                target.disputed = false;
-            }
-            ConflictType::Chargeback => {
-                if !target.disputed {
-                    return Err(PaymentError::TransactionNotDisputed { id: tx });
-                }
+        let account = self.accounts.get_mut(client);
+        account
+            .apply_conflict(conflict.type_, amount)
+            .map_err(account_error)?;
+        #[cfg(feature = "statement")]
+        account.record_conflict(tx, conflict.type_, amount);
+        *target.dispute_state = next_state;
 
-                self.accounts
-                    .get_mut(client)
-                    .chargeback(amount)
-                    .map_err(PaymentError::Chargeback)?;
-                target.disputed = false;
-            }
+        Ok(())
+    }
+}
+
+// The transaction store of a processor, bounded if
+// `EngineConfig::max_stored_transactions` is set.
+#[derive(Clone)]
+enum Transactions {
+    Unbounded(TransactionStore),
+    Bounded(BoundedTransactionStore),
+}
+
+impl Default for Transactions {
+    fn default() -> Self {
+        Self::Unbounded(TransactionStore::default())
+    }
+}
+
+impl Transactions {
+    fn len(&self) -> usize {
+        match self {
+            Self::Unbounded(store) => store.len(),
+            Self::Bounded(store) => store.len(),
         }
+    }
 
-        Ok(())
+    fn estimate_memory_bytes(&self) -> usize {
+        match self {
+            Self::Unbounded(store) => store.estimate_memory_bytes(),
+            Self::Bounded(store) => store.estimate_memory_bytes(),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Self::Unbounded(store) => store.clear(),
+            Self::Bounded(store) => store.clear(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &TransactionStoreValue> + '_> {
+        match self {
+            Self::Unbounded(store) => Box::new(store.iter().map(|(_, value)| value)),
+            Self::Bounded(store) => Box::new(store.iter().map(|(_, value)| value)),
+        }
+    }
+
+    fn get_mut(&mut self, id: TransactionId) -> TransactionResult<TransactionStoreValueMut<'_>> {
+        match self {
+            Self::Unbounded(store) => store.get_mut(id),
+            Self::Bounded(store) => store.get_mut(id),
+        }
+    }
+
+    fn lock_for_insert(&mut self, tx: Transaction) -> TransactionResult<TransactionsLock<'_>> {
+        match self {
+            Self::Unbounded(store) => store.lock_for_insert(tx).map(TransactionsLock::Unbounded),
+            Self::Bounded(store) => store.lock_for_insert(tx).map(TransactionsLock::Bounded),
+        }
+    }
+}
+
+enum TransactionsLock<'a> {
+    Unbounded(LockForInsert<'a>),
+    Bounded(BoundedLockForInsert<'a>),
+}
+
+impl TransactionsLock<'_> {
+    fn finish(self) {
+        match self {
+            Self::Unbounded(lock) => lock.finish(),
+            Self::Bounded(lock) => lock.finish(),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::num::NonZeroUsize;
+
     use itertools::Itertools;
 
-    use crate::{
-        account::{Account, AccountError},
-        operation::TransactionError,
-    };
+    use crate::operation::{TransactionError, TransactionId};
 
     use super::*;
 
     #[test]
     fn conflict_client_mismatch() {
         let mut p = PaymentProcessor::new();
-        p.transaction(Transaction::deposit(1, 1, 1)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
 
         assert_eq!(
-            p.conflict(Conflict::dispute(2, 1)),
+            p.conflict(Conflict::dispute(ClientId(2), TransactionId(1))),
             Err(PaymentError::ConflictClientMismatch {
-                tx: 1,
-                expected: 1,
-                actual: 2
+                tx: TransactionId(1),
+                expected: ClientId(1),
+                actual: ClientId(2)
             })
         );
     }
@@ -151,63 +590,562 @@ mod test {
     #[test]
     fn withdrawal_cannot_be_disputed() {
         let mut p = PaymentProcessor::new();
-        p.transaction(Transaction::deposit(1, 1, 1)).unwrap();
-        p.transaction(Transaction::withdrawal(1, 2, 1)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(2), 1))
+            .unwrap();
 
         assert_eq!(
-            p.conflict(Conflict::dispute(1, 2)),
-            Err(PaymentError::WithdrawalCannotBeDisputed { tx: 2 })
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(2))),
+            Err(PaymentError::WithdrawalCannotBeDisputed {
+                tx: TransactionId(2)
+            })
         )
     }
 
     #[test]
     fn tx_already_disputed() {
         let mut p = PaymentProcessor::new();
-        p.transaction(Transaction::deposit(1, 1, 1)).unwrap();
-        p.conflict(Conflict::dispute(1, 1)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+
+        assert_eq!(
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionAlreadyDisputed {
+                id: TransactionId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn tx_already_charged_back() {
+        let mut p = PaymentProcessor::new();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1)))
+            .unwrap();
+
+        assert_eq!(
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionAlreadyChargedBack {
+                id: TransactionId(1)
+            })
+        );
+        assert_eq!(
+            p.conflict(Conflict::resolve(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionNotDisputed {
+                id: TransactionId(1)
+            })
+        );
+        assert_eq!(
+            p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionNotDisputed {
+                id: TransactionId(1)
+            })
+        );
+        assert_eq!(
+            dispute_state(&mut p, TransactionId(1)),
+            DisputeState::Chargedback
+        );
+    }
+
+    #[test]
+    fn dispute_cycles() {
+        let mut p = PaymentProcessor::new();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        assert_eq!(dispute_state(&mut p, TransactionId(1)), DisputeState::Clean);
+
+        // a resolved transaction can be disputed again any number of times
+        for _ in 0..3 {
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+                .unwrap();
+            assert_eq!(
+                dispute_state(&mut p, TransactionId(1)),
+                DisputeState::Disputed
+            );
+            assert_eq!(
+                sorted_accounts(p.accounts()),
+                vec![Account::create(1, 0, 10, false)]
+            );
+
+            p.conflict(Conflict::resolve(ClientId(1), TransactionId(1)))
+                .unwrap();
+            assert_eq!(
+                dispute_state(&mut p, TransactionId(1)),
+                DisputeState::Resolved
+            );
+            assert_eq!(
+                sorted_accounts(p.accounts()),
+                vec![Account::create(1, 10, 0, false)]
+            );
+        }
+
+        // until it is finally charged back
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1)))
+            .unwrap();
+        assert_eq!(
+            dispute_state(&mut p, TransactionId(1)),
+            DisputeState::Chargedback
+        );
+        assert_eq!(
+            sorted_accounts(p.accounts()),
+            vec![Account::create(1, 0, 0, true)]
+        );
 
         assert_eq!(
-            p.conflict(Conflict::dispute(1, 1)),
-            Err(PaymentError::TransactionAlreadyDisputed { id: 1 })
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionAlreadyChargedBack {
+                id: TransactionId(1)
+            })
         );
     }
 
     #[test]
     fn tx_not_disputed() {
         let mut p = PaymentProcessor::new();
-        p.transaction(Transaction::deposit(1, 1, 1)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 1))
+            .unwrap();
+
+        assert_eq!(
+            p.conflict(Conflict::resolve(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionNotDisputed {
+                id: TransactionId(1)
+            })
+        );
+        assert_eq!(
+            p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1))),
+            Err(PaymentError::TransactionNotDisputed {
+                id: TransactionId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn disputes_in_flight() {
+        let mut p = PaymentProcessor::new();
+        for id in 1..=3 {
+            p.transaction(Transaction::deposit(ClientId(1), TransactionId(id), 10))
+                .unwrap();
+            p.conflict(Conflict::dispute(ClientId(1), TransactionId(id)))
+                .unwrap();
+        }
+        assert_eq!(p.disputes_in_flight(), 3);
+
+        p.conflict(Conflict::resolve(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(2)))
+            .unwrap();
+        assert_eq!(p.disputes_in_flight(), 1);
+        assert_eq!(p.stats().disputes_in_flight, 1);
+    }
+
+    #[test]
+    fn stats() {
+        let mut p = PaymentProcessor::new();
+        p.process(Transaction::deposit(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        p.process(Transaction::withdrawal(ClientId(2), TransactionId(2), 10).into())
+            .unwrap_err();
+        p.process(Conflict::dispute(ClientId(1), TransactionId(1)).into())
+            .unwrap();
+        p.process(Conflict::dispute(ClientId(1), TransactionId(1)).into())
+            .unwrap_err();
+        p.process(Conflict::resolve(ClientId(1), TransactionId(1)).into())
+            .unwrap();
+
+        assert_eq!(p.account_count(), 2);
+        assert_eq!(p.transaction_count(), 1);
+        assert_eq!(
+            p.stats(),
+            ProcessorStats {
+                transactions_processed: 2,
+                conflicts_processed: 3,
+                errors: 2,
+                accounts_managed: 2,
+                disputes_in_flight: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn checkpoint() {
+        let mut p = PaymentProcessor::new();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(2), 5))
+            .unwrap();
+        p.transaction(Transaction::deposit(ClientId(2), TransactionId(3), 20))
+            .unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.conflict(Conflict::dispute(ClientId(2), TransactionId(3)))
+            .unwrap();
+        p.conflict(Conflict::chargeback(ClientId(2), TransactionId(3)))
+            .unwrap();
+
+        let data = p.checkpoint();
+        let json = serde_json::to_string(&data).unwrap();
+        let restored: CheckpointData = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, data);
+
+        let mut restored = PaymentProcessor::from_checkpoint(restored).unwrap();
+        assert_eq!(restored.checkpoint(), data);
+        assert_eq!(
+            sorted_accounts(restored.accounts()),
+            vec![
+                Account::create(1, 5, 10, false),
+                Account::create(2, 0, 0, true),
+            ]
+        );
+
+        // the dispute states are restored as well
+        restored
+            .conflict(Conflict::resolve(ClientId(1), TransactionId(1)))
+            .unwrap();
+        assert_eq!(
+            restored.conflict(Conflict::dispute(ClientId(2), TransactionId(3))),
+            Err(PaymentError::TransactionAlreadyChargedBack {
+                id: TransactionId(3)
+            })
+        );
+
+        let mut duplicate = data.clone();
+        duplicate.transactions.push(data.transactions[0]);
+        assert!(PaymentProcessor::from_checkpoint(duplicate).is_err());
+    }
 
+    #[test]
+    fn max_stored_transactions() {
+        let config = EngineConfig {
+            max_stored_transactions: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let mut p = PaymentProcessor::with_config(&config);
+        for id in 1..=3 {
+            p.process(Transaction::deposit(ClientId(1), TransactionId(id), 10).into())
+                .unwrap();
+        }
+        assert_eq!(p.transaction_count(), 2);
+
+        // the oldest transaction was evicted
+        assert_eq!(
+            p.process(Conflict::dispute(ClientId(1), TransactionId(1)).into()),
+            Err(PaymentError::Transaction(TransactionError::NotFound {
+                id: TransactionId(1)
+            }))
+        );
+
+        // disputed transactions are kept
+        p.process(Conflict::dispute(ClientId(1), TransactionId(2)).into())
+            .unwrap();
+        p.process(Transaction::deposit(ClientId(1), TransactionId(4), 10).into())
+            .unwrap();
+        p.process(Conflict::resolve(ClientId(1), TransactionId(2)).into())
+            .unwrap();
+        assert_eq!(
+            p.process(Conflict::dispute(ClientId(1), TransactionId(3)).into()),
+            Err(PaymentError::Transaction(TransactionError::NotFound {
+                id: TransactionId(3)
+            }))
+        );
         assert_eq!(
-            p.conflict(Conflict::resolve(1, 1)),
-            Err(PaymentError::TransactionNotDisputed { id: 1 })
+            sorted_accounts(p.accounts()),
+            vec![Account::create(1, 40, 0, false)]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let config = EngineConfig {
+            max_reported_failures: 3,
+            ..Default::default()
+        };
+        let memory_estimate = Arc::new(AtomicUsize::new(0));
+        let (sink, _failures) = crossbeam::channel::unbounded();
+        let mut p = PaymentProcessor::with_config(&config)
+            .with_error_sink(sink)
+            .with_memory_estimate(memory_estimate.clone());
+        p.process(Transaction::deposit(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+        p.process(Conflict::dispute(ClientId(1), TransactionId(1)).into())
+            .unwrap();
+        memory_estimate.store(usize::MAX, Ordering::Relaxed);
+
+        p.reset();
+        assert_eq!(p.account_count(), 0);
+        assert_eq!(p.transaction_count(), 0);
+        assert_eq!(p.stats(), ProcessorStats::default());
+        assert_eq!(
+            memory_estimate.load(Ordering::Relaxed),
+            p.estimate_memory_bytes()
         );
+        assert_eq!(p.max_reported_failures, 3);
+        assert!(p.error_sink.is_some());
+
+        // the transaction id can be used again
+        p.process(Transaction::deposit(ClientId(2), TransactionId(1), 5).into())
+            .unwrap();
         assert_eq!(
-            p.conflict(Conflict::chargeback(1, 1)),
-            Err(PaymentError::TransactionNotDisputed { id: 1 })
+            sorted_accounts(p.accounts()),
+            vec![Account::create(2, 5, 0, false)]
+        );
+    }
+
+    #[test]
+    fn clone() {
+        let (sink, _failures) = crossbeam::channel::unbounded();
+        let mut p = PaymentProcessor::new()
+            .with_error_sink(sink)
+            .with_memory_estimate(Arc::default());
+        p.process(Transaction::deposit(ClientId(1), TransactionId(1), 10).into())
+            .unwrap();
+
+        let mut fork = p.clone();
+        fork.process(Transaction::withdrawal(ClientId(1), TransactionId(2), 4).into())
+            .unwrap();
+        p.process(Conflict::dispute(ClientId(1), TransactionId(1)).into())
+            .unwrap();
+
+        assert_eq!(
+            sorted_accounts(p.accounts()),
+            vec![Account::create(1, 0, 10, false)]
+        );
+        assert_eq!(
+            sorted_accounts(fork.accounts()),
+            vec![Account::create(1, 6, 0, false)]
+        );
+        assert_eq!(p.transaction_count(), 1);
+        assert_eq!(fork.transaction_count(), 2);
+
+        // the fork shares the error sink but not the memory estimate
+        let sinks = (p.error_sink.unwrap(), fork.error_sink.unwrap());
+        assert!(sinks.0.same_channel(&sinks.1));
+        let estimates = (p.memory_estimate.unwrap(), fork.memory_estimate.unwrap());
+        assert!(!Arc::ptr_eq(&estimates.0, &estimates.1));
+    }
+
+    #[test]
+    fn process_batch() {
+        let mut p = PaymentProcessor::new();
+        let results = p.process_batch(&[
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Transaction::withdrawal(ClientId(1), TransactionId(2), 20).into(),
+            Conflict::dispute(ClientId(1), TransactionId(1)).into(),
+            Conflict::resolve(ClientId(1), TransactionId(3)).into(),
+        ]);
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(()),
+                Err(PaymentError::Withdrawal(
+                    AccountError::InsufficientAvailable {
+                        needed: 20.into(),
+                        available: 10.into(),
+                        client: ClientId(1)
+                    }
+                )),
+                Ok(()),
+                Err(PaymentError::Transaction(TransactionError::NotFound {
+                    id: TransactionId(3)
+                })),
+            ]
         );
+        assert_eq!(p.accounts().get(ClientId(1)).unwrap().held(), 10.into());
+        assert_eq!(p.stats().errors, 2);
+    }
+
+    #[cfg(feature = "audit_log")]
+    #[test]
+    fn audit_log() {
+        let operations: [Operation; 3] = [
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Conflict::resolve(ClientId(1), TransactionId(1)).into(),
+            Conflict::dispute(ClientId(1), TransactionId(1)).into(),
+        ];
+
+        let mut p = PaymentProcessor::new();
+        let results = p.process_batch(&operations);
+
+        let log = p.audit_log();
+        assert_eq!(
+            log.iter().map(|entry| entry.operation).collect_vec(),
+            operations
+        );
+        assert_eq!(
+            log.iter().map(|entry| entry.result.clone()).collect_vec(),
+            results
+        );
+        assert!(log.is_sorted_by_key(|entry| entry.timestamp));
+    }
+
+    #[cfg(feature = "statement")]
+    #[test]
+    fn statement() {
+        use rust_decimal::Decimal;
+
+        use crate::account::AccountEvent;
+
+        let mut p = PaymentProcessor::new();
+        p.process_batch(&[
+            Transaction::deposit(ClientId(1), TransactionId(1), 10).into(),
+            Conflict::resolve(ClientId(1), TransactionId(1)).into(),
+            Conflict::dispute(ClientId(1), TransactionId(1)).into(),
+            Conflict::chargeback(ClientId(1), TransactionId(1)).into(),
+        ]);
+
+        let (tx, amount) = (TransactionId(1), Decimal::from(10));
+        assert_eq!(
+            p.accounts().get(ClientId(1)).unwrap().statement(),
+            vec![
+                AccountEvent::Deposited { amount },
+                AccountEvent::DisputeOpened { tx, amount },
+                AccountEvent::ChargedBack { tx, amount },
+                AccountEvent::Locked,
+            ]
+        );
+    }
+
+    #[test]
+    fn new_with_stores() {
+        let mut accounts = AccountStore::default();
+        accounts.insert(Account::create(1, 10, 0, false));
+        let mut transactions = TransactionStore::default();
+        transactions
+            .insert(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+
+        let mut p = PaymentProcessor::new_with_stores(accounts, transactions);
+        assert_eq!(p.transaction_count(), 1);
+
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+        assert_eq!(
+            sorted_accounts(p.accounts()),
+            vec![Account::create(1, 0, 10, false)]
+        );
+        assert_eq!(
+            p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 5)),
+            Err(PaymentError::Transaction(TransactionError::Conflict {
+                id: TransactionId(1)
+            }))
+        );
+    }
+
+    #[test]
+    fn inject_account() {
+        let mut p = PaymentProcessor::new();
+        p.inject_account(Account::create(1, 10, 5, false));
+        p.inject_account(Account::create(2, 7, 0, true));
+
+        p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        assert_eq!(
+            p.transaction(Transaction::withdrawal(ClientId(2), TransactionId(2), 3)),
+            Err(PaymentError::Withdrawal(AccountError::Locked {
+                client: ClientId(2)
+            }))
+        );
+
+        assert_eq!(
+            sorted_accounts(p.accounts()),
+            vec![
+                Account::create(1, 0, 5, false),
+                Account::create(2, 7, 0, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn estimate_memory_bytes() {
+        let mut p = PaymentProcessor::new();
+        assert_eq!(p.estimate_memory_bytes(), 0);
+
+        for i in 1..=100 {
+            p.transaction(Transaction::deposit(ClientId(1), TransactionId(i), 10))
+                .unwrap();
+        }
+        let estimate = p.estimate_memory_bytes();
+        assert!(
+            estimate >= 100 * size_of::<(TransactionId, crate::operation::TransactionStoreValue)>()
+        );
+
+        for i in 101..=1000 {
+            p.transaction(Transaction::deposit(ClientId(1), TransactionId(i), 10))
+                .unwrap();
+        }
+        assert!(p.estimate_memory_bytes() > estimate);
+    }
+
+    #[test]
+    fn unlock_account() {
+        let mut p = PaymentProcessor::new();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1)))
+            .unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(2), 5))
+            .unwrap();
+
+        assert_eq!(
+            p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(3), 5)),
+            Err(PaymentError::Withdrawal(AccountError::Locked {
+                client: ClientId(1)
+            }))
+        );
+
+        p.unlock_account(ClientId(1)).unwrap();
+        p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(3), 5))
+            .unwrap();
+
+        assert_eq!(
+            p.unlock_account(ClientId(2)),
+            Err(PaymentError::UnknownClient {
+                client: ClientId(2)
+            })
+        );
+        assert!(!p.accounts().contains_client(ClientId(2)));
     }
 
     #[test]
     fn payment_flow() {
         let mut p = PaymentProcessor::new();
 
-        p.transaction(Transaction::deposit(1, 1, 10)).unwrap();
-        p.transaction(Transaction::deposit(1, 2, 20)).unwrap();
-        p.transaction(Transaction::withdrawal(1, 3, 10)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(1), 10))
+            .unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(2), 20))
+            .unwrap();
+        p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(3), 10))
+            .unwrap();
         // Account { client: 1, available: 20, held: 0, locked: false }
         // Account { client: 2, available: 0, held: 0, locked: false }
 
         assert_eq!(
-            p.transaction(Transaction::deposit(2, 2, 20)),
-            Err(PaymentError::Transaction(TransactionError::Conflict {
-                id: 2
-            }))
+            p.transaction(Transaction::deposit(ClientId(2), TransactionId(2), 20)),
+            Err(PaymentError::Transaction(
+                TransactionError::DuplicateClientId {
+                    id: TransactionId(2),
+                    original_client: ClientId(1),
+                    new_client: ClientId(2)
+                }
+            ))
         );
 
-        p.transaction(Transaction::deposit(2, 4, 20)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(2), TransactionId(4), 20))
+            .unwrap();
         // Account { client: 1, available: 20, held: 0, locked: false }
         // Account { client: 2, available: 20, held: 0, locked: false }
-        p.conflict(Conflict::dispute(1, 1)).unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(1)))
+            .unwrap();
         // Account { client: 1, available: 10, held: 10, locked: false }
         // Account { client: 2, available: 20, held: 0, locked: false }
 
@@ -220,19 +1158,22 @@ mod test {
         );
 
         assert_eq!(
-            p.conflict(Conflict::dispute(2, 2)),
+            p.conflict(Conflict::dispute(ClientId(2), TransactionId(2))),
             Err(PaymentError::ConflictClientMismatch {
-                tx: 2,
-                expected: 1,
-                actual: 2
+                tx: TransactionId(2),
+                expected: ClientId(1),
+                actual: ClientId(2)
             })
         );
         assert_eq!(
-            p.conflict(Conflict::resolve(2, 4)),
-            Err(PaymentError::TransactionNotDisputed { id: 4 })
+            p.conflict(Conflict::resolve(ClientId(2), TransactionId(4))),
+            Err(PaymentError::TransactionNotDisputed {
+                id: TransactionId(4)
+            })
         );
 
-        p.conflict(Conflict::dispute(2, 4)).unwrap();
+        p.conflict(Conflict::dispute(ClientId(2), TransactionId(4)))
+            .unwrap();
         // Account { client: 1, available: 10, held: 10, locked: false }
         // Account { client: 2, available: 0, held: 20, locked: false }
 
@@ -244,7 +1185,8 @@ mod test {
             ]
         );
 
-        p.conflict(Conflict::dispute(1, 2)).unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(2)))
+            .unwrap();
         // Account { client: 1, available: -10, held: 30, locked: false }
         // Account { client: 2, available: 0, held: 20, locked: false }
 
@@ -257,11 +1199,14 @@ mod test {
         );
 
         assert_eq!(
-            p.conflict(Conflict::chargeback(1, 3)),
-            Err(PaymentError::WithdrawalCannotBeDisputed { tx: 3 })
+            p.conflict(Conflict::chargeback(ClientId(1), TransactionId(3))),
+            Err(PaymentError::WithdrawalCannotBeDisputed {
+                tx: TransactionId(3)
+            })
         );
 
-        p.conflict(Conflict::chargeback(1, 1)).unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(1)))
+            .unwrap();
         // Account { client: 1, available: -10, held: 20, locked: true }
         // Account { client: 2, available: 0, held: 20, locked: false }
 
@@ -275,12 +1220,15 @@ mod test {
 
         // withdrawal is now locked for client 1
         assert_eq!(
-            p.transaction(Transaction::withdrawal(1, 5, 15)),
-            Err(PaymentError::Withdrawal(AccountError::Locked { client: 1 }))
+            p.transaction(Transaction::withdrawal(ClientId(1), TransactionId(5), 15)),
+            Err(PaymentError::Withdrawal(AccountError::Locked {
+                client: ClientId(1)
+            }))
         );
 
         // but he can still deposit to level out debt
-        p.transaction(Transaction::deposit(1, 5, 10)).unwrap();
+        p.transaction(Transaction::deposit(ClientId(1), TransactionId(5), 10))
+            .unwrap();
         assert_eq!(
             sorted_accounts(p.accounts()),
             vec![
@@ -290,7 +1238,8 @@ mod test {
         );
 
         // disputes can still happen though
-        p.conflict(Conflict::resolve(1, 2)).unwrap();
+        p.conflict(Conflict::resolve(ClientId(1), TransactionId(2)))
+            .unwrap();
         assert_eq!(
             sorted_accounts(p.accounts()),
             vec![
@@ -299,10 +1248,12 @@ mod test {
             ]
         );
 
-        p.conflict(Conflict::dispute(1, 2)).unwrap();
+        p.conflict(Conflict::dispute(ClientId(1), TransactionId(2)))
+            .unwrap();
         // Account { client: 1, available: 0, held: 20, locked: true }
         // Account { client: 2, available: 0, held: 20, locked: false }
-        p.conflict(Conflict::chargeback(1, 2)).unwrap();
+        p.conflict(Conflict::chargeback(ClientId(1), TransactionId(2)))
+            .unwrap();
         // Account { client: 1, available: 0, held: 0, locked: true }
         // Account { client: 2, available: 0, held: 20, locked: false }
 
@@ -315,12 +1266,11 @@ mod test {
         );
     }
 
+    fn dispute_state(p: &mut PaymentProcessor, id: TransactionId) -> DisputeState {
+        *p.transactions.get_mut(id).unwrap().dispute_state
+    }
+
     fn sorted_accounts(accounts: &AccountStore) -> Vec<Account> {
-        accounts
-            .into_iter()
-            .map(|(client, acc)| (*client, acc.clone()))
-            .sorted_by_key(|(client, _)| *client)
-            .map(|(_, acc)| acc)
-            .collect_vec()
+        accounts.sorted_iter().cloned().collect_vec()
     }
 }
@@ -0,0 +1,45 @@
+#[cfg(feature = "audit_log")]
+use super::AuditEntry;
+use super::{EngineError, ProcessorStats};
+
+/// Summary of a processing run.
+#[derive(Debug, Default)]
+pub struct ProcessingReport {
+    /// Number of operations that were dispatched to the processors.
+    pub operations_received: u64,
+    /// Number of operations that were applied successfully.
+    pub operations_succeeded: u64,
+    /// Number of operations that were rejected by the processors.
+    pub operations_failed: u64,
+    /// Number of input rows that couldn't be deserialized into an operation.
+    pub skipped_rows: u64,
+    /// The first failures in input order, as pairs of the row index
+    /// and the error. Only recorded with `ErrorMode::Collect` and capped
+    /// by `EngineConfig::max_reported_failures`.
+    pub failures: Vec<(usize, EngineError)>,
+    /// Statistics of each `PaymentProcessor` worker.
+    pub processor_stats: Vec<ProcessorStats>,
+    /// Every processed operation with its result. The entries of each
+    /// worker are in processing order, one worker after another.
+    #[cfg(feature = "audit_log")]
+    pub audit_log: Vec<AuditEntry>,
+}
+
+impl ProcessingReport {
+    // Merges the report of another processor or input source into this one.
+    // Only the first `max_failures` failures in input order are kept.
+    pub(crate) fn merge(&mut self, other: Self, max_failures: usize) {
+        self.operations_received += other.operations_received;
+        self.operations_succeeded += other.operations_succeeded;
+        self.operations_failed += other.operations_failed;
+        self.skipped_rows += other.skipped_rows;
+
+        self.failures.extend(other.failures);
+        self.failures.sort_by_key(|(index, _)| *index);
+        self.failures.truncate(max_failures);
+
+        self.processor_stats.extend(other.processor_stats);
+        #[cfg(feature = "audit_log")]
+        self.audit_log.extend(other.audit_log);
+    }
+}
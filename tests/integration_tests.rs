@@ -1,7 +1,6 @@
-use std::path::PathBuf;
+use std::{cell::Cell, io::Cursor, num::NonZeroUsize, path::PathBuf};
 
-use itertools::Itertools;
-use payment_engine::PaymentEngine;
+use payment_engine::{ChunkMode, EngineError, PaymentEngine, account::AccountStore};
 
 #[test]
 fn basic() {
@@ -18,19 +17,192 @@ fn flow() {
     run_test("./tests/test_cases/flow");
 }
 
+#[test]
+fn reader() {
+    let dir = PathBuf::from("./tests/test_cases/basic");
+
+    let wanted = std::fs::read(dir.join("output.csv")).unwrap();
+
+    let input = std::fs::read(dir.join("input.csv")).unwrap();
+    let (accounts, _) = PaymentEngine::process_reader(Cursor::new(input)).unwrap();
+
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+}
+
+#[test]
+fn bytes() {
+    let dir = PathBuf::from("./tests/test_cases/flow");
+
+    let wanted = std::fs::read(dir.join("output.csv")).unwrap();
+
+    let input = std::fs::read(dir.join("input.csv")).unwrap();
+    let (accounts, _) = PaymentEngine::process_bytes(&input).unwrap();
+
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+}
+
+#[test]
+fn string() {
+    let input = "\
+type, client, tx, amount
+deposit, 1, 1, 10
+withdrawal, 1, 2, 2.5
+";
+
+    let (accounts, _) = PaymentEngine::process_string(input).unwrap();
+
+    assert_eq!(
+        String::from_utf8(to_csv(accounts)).unwrap(),
+        "\
+client,available,held,total,locked
+1,7.5000,0.0000,7.5000,false
+"
+    );
+}
+
+#[test]
+fn json() {
+    let dir = PathBuf::from("./tests/test_cases/flow");
+
+    let wanted = std::fs::read(dir.join("output.csv")).unwrap();
+
+    let input = std::fs::File::open(dir.join("input.ndjson")).unwrap();
+    let (accounts, _) = PaymentEngine::process_ndjson(input).unwrap();
+
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+}
+
+#[test]
+fn load_accounts() {
+    let path = PathBuf::from("./tests/test_cases/flow/output.csv");
+
+    let wanted = std::fs::read(&path).unwrap();
+
+    let accounts = AccountStore::load_from_csv(&path).unwrap();
+
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+}
+
+#[test]
+fn multiple_files() {
+    let dir = PathBuf::from("./tests/test_cases/multiple_files");
+
+    let wanted = std::fs::read(dir.join("output.csv")).unwrap();
+
+    let (accounts, report) =
+        PaymentEngine::process_multiple_csv(&[dir.join("input_1.csv"), dir.join("input_2.csv")])
+            .unwrap();
+
+    assert_eq!(report.operations_received, 24);
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+
+    assert!(matches!(
+        PaymentEngine::process_multiple_csv(&[dir.join("input_1.csv"), dir.join("missing.csv")]),
+        Err(EngineError::Io(_))
+    ));
+}
+
+#[test]
+fn csv_with_callback() {
+    let seen = Cell::new(0);
+    let (accounts, report) =
+        PaymentEngine::process_csv_with_callback("./tests/test_cases/basic/input.csv", |op| {
+            seen.set(seen.get() + 1);
+            op.client().get() != 2
+        })
+        .unwrap();
+
+    assert_eq!(seen.get(), 6);
+    assert_eq!(report.operations_received, 3);
+    assert_eq!(
+        String::from_utf8(to_csv(accounts)).unwrap(),
+        "\
+client,available,held,total,locked
+1,-10.5000,42.0000,31.5000,false
+"
+    );
+}
+
+#[test]
+fn csv_chunked() {
+    let path = "./tests/test_cases/basic/input.csv";
+    let chunk_size = NonZeroUsize::new(3).unwrap();
+
+    let (accounts, _) =
+        PaymentEngine::process_csv_chunked(path, chunk_size, ChunkMode::KeepTransactions).unwrap();
+    assert_eq!(
+        String::from_utf8(to_csv(accounts)).unwrap(),
+        String::from_utf8(std::fs::read("./tests/test_cases/basic/output.csv").unwrap()).unwrap()
+    );
+
+    // the dispute of the last chunk references a transaction of the first one
+    let (accounts, report) =
+        PaymentEngine::process_csv_chunked(path, chunk_size, ChunkMode::ResetTransactions).unwrap();
+    assert_eq!(report.operations_received, 6);
+    assert_eq!(
+        String::from_utf8(to_csv(accounts)).unwrap(),
+        "\
+client,available,held,total,locked
+1,31.5000,0.0000,31.5000,false
+2,3.2500,0.0000,3.2500,false
+"
+    );
+}
+
 fn run_test(dir: impl Into<PathBuf>) {
     let dir = dir.into();
 
     let wanted = std::fs::read(dir.join("output.csv")).unwrap();
 
-    let accounts = PaymentEngine::process_csv(dir.join("input.csv")).unwrap();
+    let (accounts, _) = PaymentEngine::process_csv(dir.join("input.csv")).unwrap();
+    let output = to_csv(accounts);
 
+    let wanted = String::from_utf8(wanted);
+    let output = String::from_utf8(output);
+
+    assert_eq!(wanted, output);
+}
+
+#[test]
+fn parallel_files() {
+    let dir = PathBuf::from("./tests/test_cases/parallel_files");
+
+    let wanted = std::fs::read(dir.join("output.csv")).unwrap();
+
+    let (accounts, report) =
+        PaymentEngine::process_csv_parallel(&[dir.join("input_1.csv"), dir.join("input_2.csv")])
+            .unwrap();
+
+    assert_eq!(report.operations_received, 12);
+    assert_eq!(
+        String::from_utf8(wanted),
+        String::from_utf8(to_csv(accounts))
+    );
+
+    assert!(
+        PaymentEngine::process_csv_parallel(&[dir.join("input_1.csv"), dir.join("missing.csv")])
+            .is_err()
+    );
+}
+
+fn to_csv(accounts: AccountStore) -> Vec<u8> {
     // we sort the accounts by client to be simplify the comparison
-    let accounts = accounts
-        .into_iter()
-        .sorted_by_key(|(client, _)| *client)
-        .map(|(_, acc)| acc)
-        .collect_vec();
+    let accounts = accounts.into_sorted_vec();
 
     let mut output = Vec::new();
     {
@@ -42,8 +214,5 @@ fn run_test(dir: impl Into<PathBuf>) {
         writer.flush().unwrap();
     }
 
-    let wanted = String::from_utf8(wanted);
-    let output = String::from_utf8(output);
-
-    assert_eq!(wanted, output);
+    output
 }